@@ -0,0 +1,75 @@
+use core::time::Duration;
+
+use crate::Direction;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+  Homing,
+  Opening { since: Duration },
+  Closing { since: Duration, open_time: Duration },
+  Done { open_time: Duration, close_time: Duration },
+}
+
+/// The next step of a `Calibration`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalibrationStep {
+  /// Start moving the blind in the given direction.
+  Move(Direction),
+  /// The calibration is done.
+  Done { open_time: Duration, close_time: Duration },
+}
+
+/// Measures the travel times of a blind.
+///
+/// The blind is first closed fully, then opened and closed again, while the time between
+/// reaching the end stops is measured. Timestamps are given as the time elapsed since an
+/// arbitrary, but fixed point in time, like for `PositionEstimator`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Calibration {
+  state: State,
+}
+
+impl Calibration {
+  /// Start a new calibration. The blind has to be moved in the returned direction.
+  pub const fn start() -> (Self, Direction) {
+    (Self { state: State::Homing }, Direction::Down)
+  }
+
+  /// Notify the calibration that the blind reached an end stop at the given timestamp.
+  ///
+  /// Returns the next step, where `CalibrationStep::Move` has to be sent immediately.
+  pub fn end_stop(&mut self, now: Duration) -> CalibrationStep {
+    let (state, step) = match self.state {
+      State::Homing => (State::Opening { since: now }, CalibrationStep::Move(Direction::Up)),
+      State::Opening { since } => {
+        (State::Closing { since: now, open_time: now.saturating_sub(since) }, CalibrationStep::Move(Direction::Down))
+      },
+      State::Closing { since, open_time } => {
+        let close_time = now.saturating_sub(since);
+        (State::Done { open_time, close_time }, CalibrationStep::Done { open_time, close_time })
+      },
+      State::Done { open_time, close_time } => (self.state, CalibrationStep::Done { open_time, close_time }),
+    };
+
+    self.state = state;
+    step
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_calibration() {
+    let (mut calibration, direction) = Calibration::start();
+    assert_eq!(direction, Direction::Down);
+
+    assert_eq!(calibration.end_stop(Duration::from_secs(30)), CalibrationStep::Move(Direction::Up));
+    assert_eq!(calibration.end_stop(Duration::from_secs(50)), CalibrationStep::Move(Direction::Down));
+
+    let done = CalibrationStep::Done { open_time: Duration::from_secs(20), close_time: Duration::from_secs(18) };
+    assert_eq!(calibration.end_stop(Duration::from_secs(68)), done);
+    assert_eq!(calibration.end_stop(Duration::from_secs(100)), done);
+  }
+}
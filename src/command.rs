@@ -9,13 +9,15 @@ impl fmt::Display for UnknownCommand {
   }
 }
 
+#[cfg(feature = "std")]
 impl std::error::Error for UnknownCommand {
   fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
     None
   }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
 #[repr(u8)]
 pub enum Command {
   My       = 0x1 << 4,
@@ -56,3 +58,24 @@ impl FromStr for Command {
     Err(UnknownCommand)
   }
 }
+
+impl TryFrom<u8> for Command {
+  type Error = UnknownCommand;
+
+  /// Convert the upper nibble of the command byte to a `Command`.
+  fn try_from(value: u8) -> Result<Self, Self::Error> {
+    Ok(match value {
+      0x10 => Command::My,
+      0x20 => Command::Up,
+      0x30 => Command::MyUp,
+      0x40 => Command::Down,
+      0x50 => Command::MyDown,
+      0x60 => Command::UpDown,
+      0x70 => Command::MyUpDown,
+      0x80 => Command::Prog,
+      0x90 => Command::SunFlag,
+      0xA0 => Command::Flag,
+      _ => return Err(UnknownCommand),
+    })
+  }
+}
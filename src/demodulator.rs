@@ -0,0 +1,267 @@
+use embedded_hal::digital::PinState::{self, *};
+
+use crate::{Frame, FrameError, TimingProfile};
+
+/// Maximum deviation of received pulse durations from the nominal timing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tolerance {
+  /// Maximum deviation of Manchester-encoded symbols in µs.
+  pub symbol: u32,
+  /// Maximum deviation of hardware and software sync pulses in µs.
+  pub sync: u32,
+}
+
+impl Tolerance {
+  /// The tolerance for receiving frames sent with the given `timing`, relative to its symbol width.
+  pub const fn for_timing(timing: &TimingProfile) -> Self {
+    Self { symbol: timing.symbol_width / 5, sync: timing.symbol_width / 2 }
+  }
+}
+
+impl Default for Tolerance {
+  fn default() -> Self {
+    Self::for_timing(&TimingProfile::SOMFY)
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+  Idle,
+  HardwareSync { count: usize },
+  SoftwareSync,
+  Data { bytes: [u8; 7], bits: usize, half_symbol: Option<PinState> },
+}
+
+/// Decodes a stream of Manchester-encoded pulses into `Frame`s.
+///
+/// Each pulse is given as a `PinState` together with its duration in µs.
+#[derive(Debug, Clone)]
+pub struct Demodulator {
+  timing: TimingProfile,
+  tolerance: Tolerance,
+  state: State,
+}
+
+impl Default for Demodulator {
+  fn default() -> Self {
+    Self::new(Tolerance::default())
+  }
+}
+
+impl Demodulator {
+  pub const fn new(tolerance: Tolerance) -> Self {
+    Self::with_timing(TimingProfile::SOMFY, tolerance)
+  }
+
+  pub const fn with_timing(timing: TimingProfile, tolerance: Tolerance) -> Self {
+    Self { timing, tolerance, state: State::Idle }
+  }
+
+  pub fn timing(&self) -> &TimingProfile {
+    &self.timing
+  }
+
+  pub fn tolerance(&self) -> Tolerance {
+    self.tolerance
+  }
+
+  /// Discard any partially received frame.
+  pub fn reset(&mut self) {
+    self.state = State::Idle;
+  }
+
+  /// Feed a single pulse into the demodulator.
+  ///
+  /// Returns a result once all 56 bits of a frame have been received.
+  pub fn feed(&mut self, state: PinState, duration: u32) -> Option<Result<Frame, FrameError>> {
+    match self.state {
+      State::Idle | State::HardwareSync { .. } | State::SoftwareSync => self.feed_sync(state, duration),
+      State::Data { .. } => {
+        let half_symbols = match self.half_symbols(duration) {
+          Some(half_symbols) => half_symbols,
+          // The last half symbol of a frame may be merged with the inter-frame gap.
+          None if state == Low && self.remaining_half_symbols() == 1 => 1,
+          None => {
+            self.reset();
+            return self.feed_sync(state, duration)
+          },
+        };
+
+        let mut result = None;
+        for _ in 0..half_symbols {
+          if let Some(frame) = self.push_half_symbol(state) {
+            result = Some(frame);
+          }
+        }
+        result
+      },
+    }
+  }
+
+  /// Decode all frames contained in the given `pulses`.
+  pub fn demodulate<'a, I>(&'a mut self, pulses: I) -> impl Iterator<Item = Result<Frame, FrameError>> + 'a
+  where
+    I: IntoIterator<Item = (PinState, u32)>,
+    I::IntoIter: 'a,
+  {
+    pulses.into_iter().filter_map(move |(state, duration)| self.feed(state, duration))
+  }
+
+  fn feed_sync(&mut self, state: PinState, duration: u32) -> Option<Result<Frame, FrameError>> {
+    let hardware_sync = within(duration, 2 * self.timing.symbol_width, self.tolerance.sync);
+
+    self.state = match (self.state, state) {
+      (State::HardwareSync { count }, High) if hardware_sync => State::HardwareSync { count: count + 1 },
+      (State::HardwareSync { count }, Low) if hardware_sync => State::HardwareSync { count },
+      (State::HardwareSync { count }, High)
+        if count >= self.timing.hardware_sync_once
+          && within(duration, self.timing.software_sync, self.tolerance.sync) =>
+      {
+        State::SoftwareSync
+      },
+      (State::SoftwareSync, Low) => match self.half_symbols(duration) {
+        // The low half of the software sync may be merged with the first half of the first bit.
+        Some(1) => State::Data { bytes: [0; 7], bits: 0, half_symbol: None },
+        Some(2) => State::Data { bytes: [0; 7], bits: 0, half_symbol: Some(Low) },
+        _ => State::Idle,
+      },
+      (_, High) if hardware_sync => State::HardwareSync { count: 1 },
+      _ => State::Idle,
+    };
+
+    None
+  }
+
+  fn push_half_symbol(&mut self, state: PinState) -> Option<Result<Frame, FrameError>> {
+    let State::Data { ref mut bytes, ref mut bits, ref mut half_symbol } = self.state else { return None };
+
+    let bit = match (half_symbol.take(), state) {
+      (None, state) => {
+        *half_symbol = Some(state);
+        return None
+      },
+      (Some(Low), High) => true,
+      (Some(High), Low) => false,
+      // Invalid Manchester encoding.
+      _ => {
+        self.reset();
+        return None
+      },
+    };
+
+    if bit {
+      bytes[*bits / 8] |= 1 << (7 - *bits % 8);
+    }
+    *bits += 1;
+
+    if *bits == bytes.len() * 8 {
+      let bytes = *bytes;
+      self.reset();
+      return Some(Frame::from_bytes(bytes))
+    }
+
+    None
+  }
+
+  fn remaining_half_symbols(&self) -> usize {
+    match self.state {
+      State::Data { bytes, bits, half_symbol } => (bytes.len() * 8 - bits) * 2 - usize::from(half_symbol.is_some()),
+      _ => 0,
+    }
+  }
+
+  fn half_symbols(&self, duration: u32) -> Option<usize> {
+    if within(duration, self.timing.symbol_width / 2, self.tolerance.symbol) {
+      Some(1)
+    } else if within(duration, self.timing.symbol_width, self.tolerance.symbol) {
+      Some(2)
+    } else {
+      None
+    }
+  }
+}
+
+fn within(duration: u32, nominal: u32, tolerance: u32) -> bool {
+  duration.abs_diff(nominal) <= tolerance
+}
+
+#[cfg(test)]
+mod tests {
+  use ux::u24;
+
+  use super::*;
+  use crate::{Command, Waveform};
+
+  fn frame(command: Command, rolling_code: u16) -> Frame {
+    Frame::builder()
+      .key(0xA7)
+      .command(command)
+      .rolling_code(rolling_code)
+      .remote_address(u24::new(0xFFAA11))
+      .build()
+      .expect("Failed to build frame")
+  }
+
+  fn pulses(frame: &Frame, repetitions: usize) -> impl Iterator<Item = (PinState, u32)> {
+    Waveform::for_frame(frame, repetitions).into_pulses().into_iter().map(Into::into)
+  }
+
+  #[test]
+  fn test_demodulate() {
+    for (command, rolling_code) in [(Command::Up, 42), (Command::Down, 0xFFFF), (Command::My, 0)] {
+      let frame = frame(command, rolling_code);
+
+      let mut demodulator = Demodulator::default();
+      let frames = demodulator.demodulate(pulses(&frame, 2)).collect::<Vec<_>>();
+
+      assert_eq!(frames, vec![Ok(frame); 3]);
+    }
+  }
+
+  #[test]
+  fn test_demodulate_with_jitter() {
+    let frame = frame(Command::Prog, 1337);
+
+    let mut demodulator = Demodulator::default();
+    let jittered =
+      pulses(&frame, 0)
+        .enumerate()
+        .map(|(i, (state, duration))| if i % 2 == 0 { (state, duration + 100) } else { (state, duration - 100) });
+    let frames = demodulator.demodulate(jittered).collect::<Vec<_>>();
+
+    assert_eq!(frames, vec![Ok(frame)]);
+  }
+
+  #[test]
+  fn test_demodulate_ignores_noise() {
+    let frame = frame(Command::Down, 7);
+
+    let timing = TimingProfile::SOMFY;
+    let noise = [
+      (High, 300),
+      (Low, 5_000),
+      (High, 2 * timing.symbol_width),
+      (Low, 700),
+      (High, timing.software_sync),
+      (Low, 100),
+    ];
+
+    let mut demodulator = Demodulator::default();
+    let frames = demodulator.demodulate(noise.into_iter().chain(pulses(&frame, 0))).collect::<Vec<_>>();
+
+    assert_eq!(frames, vec![Ok(frame)]);
+  }
+
+  #[test]
+  fn test_demodulate_with_timing() {
+    let frame = frame(Command::MyUp, 999);
+
+    let timing = TimingProfile { symbol_width: 1000, ..TimingProfile::SOMFY };
+    let pulses = Waveform::for_frame_with_timing(&frame, 1, &timing).into_pulses().into_iter().map(Into::into);
+
+    let mut demodulator = Demodulator::with_timing(timing, Tolerance::for_timing(&timing));
+    let frames = demodulator.demodulate(pulses).collect::<Vec<_>>();
+
+    assert_eq!(frames, vec![Ok(frame); 2]);
+  }
+}
@@ -0,0 +1,56 @@
+use core::{fmt, str::FromStr, time::Duration};
+
+use crate::Command;
+
+#[derive(Debug)]
+pub struct UnknownDirection;
+
+impl fmt::Display for UnknownDirection {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Unknown direction")
+  }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for UnknownDirection {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    None
+  }
+}
+
+/// Direction in which a blind moves or its slats are tilted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+pub enum Direction {
+  Up,
+  Down,
+}
+
+impl Direction {
+  /// Minimum duration of a press which venetian motors interpret as full travel instead of a tilt step.
+  pub const TRAVEL_HOLD: Duration = Duration::from_secs(1);
+
+  /// The `Command` for moving in this direction.
+  pub const fn command(self) -> Command {
+    match self {
+      Self::Up => Command::Up,
+      Self::Down => Command::Down,
+    }
+  }
+}
+
+impl FromStr for Direction {
+  type Err = UnknownDirection;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let directions = [("up", Direction::Up), ("down", Direction::Down)];
+
+    for (string, variant) in directions {
+      if s.eq_ignore_ascii_case(string) {
+        return Ok(variant)
+      }
+    }
+
+    Err(UnknownDirection)
+  }
+}
@@ -0,0 +1,64 @@
+//! Helpers for sharing files between processes, as used by `FileStorage`.
+
+use std::{
+  ffi::OsString,
+  fs::{self, File},
+  io::{self, Write},
+  path::{Path, PathBuf},
+};
+
+use fs4::FileExt;
+
+/// Take an exclusive advisory lock for the file at `path`, which is held until the returned file is dropped.
+///
+/// A separate lock file is used, since `write_file` replaces the file itself.
+pub fn lock_file(path: &Path) -> io::Result<File> {
+  let mut lock_path = OsString::from(path);
+  lock_path.push(".lock");
+
+  let file = File::options().create(true).truncate(false).write(true).open(lock_path)?;
+  FileExt::lock(&file)?;
+  Ok(file)
+}
+
+/// Atomically replace the file at `path` with `contents`.
+///
+/// The contents are written to a temporary file, which is synced to disk and then renamed, so that
+/// the file is never left truncated, e.g. by a power cut.
+pub fn write_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+  let mut tmp_path = OsString::from(path);
+  tmp_path.push(".tmp");
+  let tmp_path = PathBuf::from(tmp_path);
+
+  let mut file = File::create(&tmp_path)?;
+  if let Ok(metadata) = fs::metadata(path) {
+    file.set_permissions(metadata.permissions())?;
+  }
+  file.write_all(contents)?;
+  file.sync_all()?;
+  drop(file);
+
+  fs::rename(&tmp_path, path)?;
+
+  // Sync the directory so the rename itself survives a power cut.
+  let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+  File::open(dir)?.sync_all()?;
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_write_file_relative() {
+    // A bare file name has an empty parent, so the current directory needs to be synced.
+    let file = tempfile::NamedTempFile::new_in(".").unwrap();
+    let path = Path::new(file.path().file_name().unwrap());
+    assert_eq!(path.parent(), Some(Path::new("")));
+
+    write_file(path, b"contents").unwrap();
+    assert_eq!(fs::read(file.path()).unwrap(), b"contents");
+  }
+}
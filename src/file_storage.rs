@@ -0,0 +1,1009 @@
+use std::{
+  collections::BTreeMap,
+  fmt,
+  fs::{self, File},
+  io::{self, Read},
+  path::{Path, PathBuf},
+  time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use ux::u24;
+
+use crate::{
+  file_io::{lock_file, write_file},
+  KeyStrategy, PositionEstimator, Remote, RollingCodeStorage,
+};
+
+/// An error returned by `FileStorage`.
+#[derive(Debug)]
+pub enum FileStorageError {
+  /// Reading, writing or locking the file failed.
+  Io(io::Error),
+  /// The file extension does not correspond to a supported `FileFormat`.
+  UnsupportedFormat(PathBuf),
+  /// The file was written by a newer version with an unknown format.
+  UnsupportedVersion(u32),
+  /// The YAML file could not be parsed or written.
+  Yaml(serde_yaml::Error),
+  /// The JSON file could not be parsed or written.
+  Json(serde_json::Error),
+  /// The TOML file could not be parsed.
+  TomlDe(toml::de::Error),
+  /// The TOML file could not be written.
+  TomlSer(toml::ser::Error),
+  /// No remote with the given name exists.
+  UnknownRemote(String),
+  /// No remote with the given address exists.
+  UnknownAddress(u24),
+  /// A remote with the given name already exists.
+  DuplicateName(String),
+  /// A remote with the given address already exists.
+  DuplicateAddress(u24),
+  /// The config file cannot be modified, since a separate state file is used.
+  ReadOnly(PathBuf),
+}
+
+impl fmt::Display for FileStorageError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Io(err) => err.fmt(f),
+      Self::UnsupportedFormat(path) => write!(f, "Unsupported file format for {}", path.display()),
+      Self::UnsupportedVersion(version) => write!(f, "Unsupported config version {version}"),
+      Self::Yaml(err) => write!(f, "Invalid YAML: {err}"),
+      Self::Json(err) => write!(f, "Invalid JSON: {err}"),
+      Self::TomlDe(err) => write!(f, "Invalid TOML: {err}"),
+      Self::TomlSer(err) => write!(f, "Failed to write TOML: {err}"),
+      Self::UnknownRemote(name) => write!(f, "No remote with name “{name}” found"),
+      Self::UnknownAddress(address) => write!(f, "No remote with address {address} found"),
+      Self::DuplicateName(name) => write!(f, "Remote “{name}” already exists"),
+      Self::DuplicateAddress(address) => write!(f, "A remote with address {address} already exists"),
+      Self::ReadOnly(path) => {
+        write!(f, "Config file {} is read-only, since a separate state file is used", path.display())
+      },
+    }
+  }
+}
+
+impl std::error::Error for FileStorageError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Self::Io(err) => Some(err),
+      Self::Yaml(err) => Some(err),
+      Self::Json(err) => Some(err),
+      Self::TomlDe(err) => Some(err),
+      Self::TomlSer(err) => Some(err),
+      _ => None,
+    }
+  }
+}
+
+impl From<io::Error> for FileStorageError {
+  fn from(err: io::Error) -> Self {
+    Self::Io(err)
+  }
+}
+
+/// File format of a `FileStorage`, chosen by file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+  Yaml,
+  Json,
+  Toml,
+}
+
+impl FileFormat {
+  /// Determine the format from the extension of `path`.
+  pub fn from_path(path: &Path) -> Option<Self> {
+    let extension = path.extension()?.to_str()?;
+
+    let formats = [("yaml", Self::Yaml), ("yml", Self::Yaml), ("json", Self::Json), ("toml", Self::Toml)];
+
+    for (name, format) in formats {
+      if extension.eq_ignore_ascii_case(name) {
+        return Some(format)
+      }
+    }
+
+    None
+  }
+
+  /// Parse `contents` in this format.
+  pub fn deserialize<T>(self, contents: &str) -> Result<T, FileStorageError>
+  where
+    T: for<'de> Deserialize<'de>,
+  {
+    match self {
+      Self::Yaml => serde_yaml::from_str(contents).map_err(FileStorageError::Yaml),
+      Self::Json => serde_json::from_str(contents).map_err(FileStorageError::Json),
+      Self::Toml => toml::from_str(contents).map_err(FileStorageError::TomlDe),
+    }
+  }
+
+  /// Write `value` in this format.
+  pub fn serialize<T>(self, value: &T) -> Result<String, FileStorageError>
+  where
+    T: Serialize,
+  {
+    match self {
+      Self::Yaml => serde_yaml::to_string(value).map_err(FileStorageError::Yaml),
+      Self::Json => serde_json::to_string_pretty(value).map_err(FileStorageError::Json),
+      Self::Toml => toml::to_string(value).map_err(FileStorageError::TomlSer),
+    }
+  }
+}
+
+/// Current version of the file format written by `FileStorage`.
+///
+/// Version 0 is the original format, which only contained a flat map of remotes.
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Global settings stored next to the remotes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+  /// GPIO pin of the default transmitter.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub transmitter_pin: Option<u8>,
+  /// GPIO pin of the receiver.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub receiver_pin: Option<u8>,
+  /// Name of the `TimingProfile`, e.g. `somfy`.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub timing: Option<String>,
+  /// Timing correction in microseconds, see `TimingProfile::with_correction`.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub timing_correction: Option<i32>,
+}
+
+/// Type of the device controlled by a remote.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceType {
+  #[default]
+  RollerShutter,
+  /// A venetian blind with tiltable slats.
+  VenetianBlind,
+  Awning,
+}
+
+/// A remote together with the configuration of the device it controls.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "RemoteEntry", into = "RemoteEntry")]
+pub struct RemoteConfig {
+  pub remote: Remote,
+  pub device: DeviceType,
+  /// Room the device is located in.
+  pub room: Option<String>,
+  /// Time needed to open the blind fully.
+  pub open_time: Option<Duration>,
+  /// Time needed to close the blind fully.
+  pub close_time: Option<Duration>,
+  /// Favourite position in percent, which the blind moves to when My is sent while it is stopped.
+  pub my_position: Option<u8>,
+  /// Number of repetitions used when sending a command without holding the button.
+  pub repetitions: Option<usize>,
+  /// GPIO pin of the transmitter used for this remote instead of the default one.
+  pub transmitter_pin: Option<u8>,
+}
+
+impl RemoteConfig {
+  /// Whether the device is a venetian blind with tiltable slats.
+  pub fn is_venetian(&self) -> bool {
+    self.device == DeviceType::VenetianBlind
+  }
+
+  /// Create a `PositionEstimator` starting at the given `position`, if the travel times are configured.
+  pub fn estimator(&self, position: u8) -> Option<PositionEstimator> {
+    Some(PositionEstimator::new(self.open_time?, self.close_time?, position).with_my_position(self.my_position))
+  }
+}
+
+impl From<Remote> for RemoteConfig {
+  fn from(remote: Remote) -> Self {
+    Self {
+      remote,
+      device: DeviceType::default(),
+      room: None,
+      open_time: None,
+      close_time: None,
+      my_position: None,
+      repetitions: None,
+      transmitter_pin: None,
+    }
+  }
+}
+
+// Flattening `Remote` would break YAML tags for enums like `KeyStrategy`, so its fields are repeated here.
+#[derive(Serialize, Deserialize)]
+struct RemoteEntry {
+  address: u24,
+  rolling_code: u16,
+  #[serde(default)]
+  key: KeyStrategy,
+  #[serde(default, skip_serializing_if = "is_default")]
+  device: DeviceType,
+  // Only used by version 0, replaced by `device`.
+  #[serde(default, skip_serializing)]
+  venetian: bool,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  room: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none", with = "humantime_serde")]
+  open_time: Option<Duration>,
+  #[serde(default, skip_serializing_if = "Option::is_none", with = "humantime_serde")]
+  close_time: Option<Duration>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  my_position: Option<u8>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  repetitions: Option<usize>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  transmitter_pin: Option<u8>,
+}
+
+impl From<RemoteEntry> for RemoteConfig {
+  fn from(entry: RemoteEntry) -> Self {
+    Self {
+      remote: Remote::new(entry.address, entry.rolling_code).with_key(entry.key),
+      device: if entry.venetian { DeviceType::VenetianBlind } else { entry.device },
+      room: entry.room,
+      open_time: entry.open_time,
+      close_time: entry.close_time,
+      my_position: entry.my_position,
+      repetitions: entry.repetitions,
+      transmitter_pin: entry.transmitter_pin,
+    }
+  }
+}
+
+impl From<RemoteConfig> for RemoteEntry {
+  fn from(config: RemoteConfig) -> Self {
+    let RemoteConfig { remote, device, room, open_time, close_time, my_position, repetitions, transmitter_pin } =
+      config;
+    Self {
+      address: remote.address(),
+      rolling_code: remote.rolling_code(),
+      key: remote.key(),
+      device,
+      venetian: false,
+      room,
+      open_time,
+      close_time,
+      my_position,
+      repetitions,
+      transmitter_pin,
+    }
+  }
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+  *value == T::default()
+}
+
+#[derive(Deserialize)]
+struct Version {
+  version: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct Document {
+  #[serde(default)]
+  settings: Settings,
+  #[serde(default)]
+  remotes: BTreeMap<String, RemoteConfig>,
+}
+
+#[derive(Serialize)]
+struct DocumentRef<'a> {
+  version: u32,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  settings: Option<&'a Settings>,
+  remotes: &'a BTreeMap<String, RemoteConfig>,
+}
+
+/// Current version of the state file written by `FileStorage::open_with_state`.
+const STATE_VERSION: u32 = 1;
+
+// Values changed at runtime, which override the ones from the config file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct RemoteState {
+  rolling_code: u16,
+  #[serde(default, skip_serializing_if = "Option::is_none", with = "humantime_serde")]
+  open_time: Option<Duration>,
+  #[serde(default, skip_serializing_if = "Option::is_none", with = "humantime_serde")]
+  close_time: Option<Duration>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  my_position: Option<u8>,
+}
+
+// Remotes are keyed by their hexadecimal address, so renaming a remote in the config file keeps its state.
+#[derive(Serialize, Deserialize)]
+struct StateDocument {
+  version: u32,
+  #[serde(default)]
+  remotes: BTreeMap<String, RemoteState>,
+}
+
+/// A `RollingCodeStorage` keeping all remotes in a single YAML, JSON or TOML file.
+///
+/// The file is always replaced atomically and locked while it is modified, so it can safely be
+/// shared between multiple processes.
+///
+/// When opened with `FileStorage::open_with_state`, the config file is never written and rolling codes,
+/// travel times and favourite positions are kept in a separate state file instead.
+#[derive(Debug)]
+pub struct FileStorage {
+  path: PathBuf,
+  format: FileFormat,
+  state_file: Option<(PathBuf, FileFormat)>,
+  state: BTreeMap<u24, RemoteState>,
+  settings: Settings,
+  reservation_size: u16,
+  address_map: BTreeMap<u24, String>,
+  remotes: BTreeMap<String, RemoteConfig>,
+  /// Rolling codes as last read or persisted by this process, to detect codes used by other processes.
+  rolling_codes: BTreeMap<u24, u16>,
+}
+
+impl FileStorage {
+  /// Open the file at `path`, creating it if it does not exist yet.
+  ///
+  /// The format is chosen by the file extension, see `FileFormat::from_path`.
+  pub fn open(path: impl AsRef<Path>) -> Result<Self, FileStorageError> {
+    Self::open_inner(path.as_ref(), None)
+  }
+
+  /// Open the existing config file at `path` read-only, keeping all values changed at runtime
+  /// in the state file at `state_path` instead, which is created if it does not exist yet.
+  ///
+  /// Values in the state file override the ones in the config file.
+  pub fn open_with_state(path: impl AsRef<Path>, state_path: impl AsRef<Path>) -> Result<Self, FileStorageError> {
+    let state_path = state_path.as_ref();
+    let format =
+      FileFormat::from_path(state_path).ok_or_else(|| FileStorageError::UnsupportedFormat(state_path.into()))?;
+
+    Self::open_inner(path.as_ref(), Some((state_path.into(), format)))
+  }
+
+  fn open_inner(path: &Path, state_file: Option<(PathBuf, FileFormat)>) -> Result<Self, FileStorageError> {
+    let format = FileFormat::from_path(path).ok_or_else(|| FileStorageError::UnsupportedFormat(path.into()))?;
+
+    let mut storage = Self {
+      path: path.into(),
+      format,
+      state_file,
+      state: BTreeMap::new(),
+      settings: Settings::default(),
+      reservation_size: 1,
+      address_map: BTreeMap::new(),
+      remotes: BTreeMap::new(),
+      rolling_codes: BTreeMap::new(),
+    };
+
+    // Create missing files and upgrade old versions in place, unless the config file is read-only.
+    if storage.state_file.is_some() {
+      storage.reload()?;
+    } else if !storage.path.exists() {
+      storage.update(|_| Ok(()))?;
+    } else {
+      let version = storage.reload()?;
+      if version < CONFIG_VERSION {
+        log::info!("Upgrading {} from version {version} to {CONFIG_VERSION}.", storage.path.display());
+        storage.update(|_| Ok(()))?;
+      }
+    }
+    storage.rolling_codes =
+      storage.remotes.values().map(|config| (config.remote.address(), config.remote.rolling_code())).collect();
+
+    Ok(storage)
+  }
+
+  /// The path of the underlying file.
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /// Set the number of rolling codes reserved with a single write.
+  pub fn reserve(&mut self, reservation_size: u16) -> &mut Self {
+    self.reservation_size = reservation_size;
+    self
+  }
+
+  pub fn settings(&self) -> &Settings {
+    &self.settings
+  }
+
+  pub fn remote(&self, name: &str) -> Option<&Remote> {
+    self.remotes.get(name).map(|config| &config.remote)
+  }
+
+  pub fn remote_config(&self, name: &str) -> Option<&RemoteConfig> {
+    self.remotes.get(name)
+  }
+
+  pub fn remote_name(&self, address: u24) -> Option<&str> {
+    self.address_map.get(&address).map(|name| name.as_str())
+  }
+
+  /// Add a new remote and write it to the file.
+  pub fn add_remote(&mut self, name: String, remote: Remote) -> Result<(), FileStorageError> {
+    self.check_writable()?;
+    self.update(|storage| {
+      if storage.remotes.contains_key(&name) {
+        return Err(FileStorageError::DuplicateName(name))
+      }
+
+      if storage.address_map.contains_key(&remote.address()) {
+        return Err(FileStorageError::DuplicateAddress(remote.address()))
+      }
+
+      storage.address_map.insert(remote.address(), name.clone());
+      storage.remotes.insert(name, remote.into());
+      Ok(())
+    })
+  }
+
+  /// Remove a remote and write the change to the file.
+  pub fn remove_remote(&mut self, name: &str) -> Result<Option<Remote>, FileStorageError> {
+    self.check_writable()?;
+    self.update(|storage| {
+      let Some(RemoteConfig { remote, .. }) = storage.remotes.remove(name) else { return Ok(None) };
+      storage.address_map.remove(&remote.address());
+
+      Ok(Some(remote))
+    })
+  }
+
+  /// Rename a remote and write the change to the file.
+  pub fn rename_remote(&mut self, name: &str, new_name: String) -> Result<(), FileStorageError> {
+    self.check_writable()?;
+    self.update(|storage| {
+      if storage.remotes.contains_key(&new_name) {
+        return Err(FileStorageError::DuplicateName(new_name))
+      }
+
+      let config = storage.remotes.remove(name).ok_or_else(|| FileStorageError::UnknownRemote(name.to_owned()))?;
+      storage.address_map.insert(config.remote.address(), new_name.clone());
+      storage.remotes.insert(new_name, config);
+      Ok(())
+    })
+  }
+
+  /// Change the address of a remote and write it to the file.
+  ///
+  /// Motors paired with the old address no longer react to the remote.
+  pub fn set_address(&mut self, name: &str, address: u24) -> Result<(), FileStorageError> {
+    self.check_writable()?;
+    self.update(|storage| {
+      if storage.address_map.get(&address).is_some_and(|other| other != name) {
+        return Err(FileStorageError::DuplicateAddress(address))
+      }
+
+      let config = storage.remote_config_mut(name)?;
+      let old_address = config.remote.address();
+      config.remote = Remote::new(address, config.remote.rolling_code()).with_key(config.remote.key());
+
+      storage.address_map.remove(&old_address);
+      storage.address_map.insert(address, name.to_owned());
+      if let Some(rolling_code) = storage.rolling_codes.remove(&old_address) {
+        storage.rolling_codes.insert(address, rolling_code);
+      }
+      Ok(())
+    })
+  }
+
+  /// Set the rolling code of a remote and write it to the file, e.g. after restoring a backup.
+  pub fn set_rolling_code(&mut self, name: &str, rolling_code: u16) -> Result<(), FileStorageError> {
+    self.update(|storage| {
+      let config = storage.remote_config_mut(name)?;
+      config.remote = Remote::new(config.remote.address(), rolling_code).with_key(config.remote.key());
+
+      let address = config.remote.address();
+      storage.rolling_codes.insert(address, rolling_code);
+      Ok(())
+    })
+  }
+
+  /// Set the favourite position of a remote and write it to the file.
+  pub fn set_my_position(&mut self, name: &str, my_position: Option<u8>) -> Result<(), FileStorageError> {
+    self.update(|storage| {
+      let config = storage.remote_config_mut(name)?;
+      config.my_position = my_position;
+
+      let address = config.remote.address();
+      if let Some(state) = storage.remote_state_mut(address) {
+        state.my_position = my_position;
+      }
+      Ok(())
+    })
+  }
+
+  /// Set the travel times of a remote, rounded to milliseconds, and write them to the file.
+  pub fn set_travel_times(
+    &mut self,
+    name: &str,
+    open_time: Duration,
+    close_time: Duration,
+  ) -> Result<(), FileStorageError> {
+    let round = |duration: Duration| Duration::from_millis((duration.as_micros() as u64 + 500) / 1000);
+    let (open_time, close_time) = (round(open_time), round(close_time));
+
+    self.update(|storage| {
+      let config = storage.remote_config_mut(name)?;
+      config.open_time = Some(open_time);
+      config.close_time = Some(close_time);
+
+      let address = config.remote.address();
+      if let Some(state) = storage.remote_state_mut(address) {
+        state.open_time = Some(open_time);
+        state.close_time = Some(close_time);
+      }
+      Ok(())
+    })
+  }
+
+  /// Pick up rolling codes used by other processes for the given `remote`.
+  ///
+  /// If the rolling code in the file changed since it was last read or written by this
+  /// process, `remote` is replaced with the stored one, dropping any reserved rolling codes.
+  pub fn refresh(&mut self, remote: &mut Remote) -> Result<(), FileStorageError> {
+    let _lock = self.lock()?;
+    self.reload()?;
+
+    let Some(stored) = self.remote_by_address(remote.address()).map(|config| config.remote.clone()) else {
+      return Ok(())
+    };
+
+    if self
+      .rolling_codes
+      .insert(remote.address(), stored.rolling_code())
+      .is_some_and(|known| known != stored.rolling_code())
+    {
+      log::warn!("Rolling code of remote {} was changed by another process.", remote.address());
+      *remote = stored;
+    }
+
+    Ok(())
+  }
+
+  /// Generate a random address which is not used by any remote yet.
+  pub fn unused_address(&self) -> u24 {
+    crate::unused_address(|address| self.address_map.contains_key(&address))
+  }
+
+  pub fn remotes(&self) -> &BTreeMap<String, RemoteConfig> {
+    &self.remotes
+  }
+}
+
+impl FileStorage {
+  fn remote_config_mut(&mut self, name: &str) -> Result<&mut RemoteConfig, FileStorageError> {
+    self.remotes.get_mut(name).ok_or_else(|| FileStorageError::UnknownRemote(name.to_owned()))
+  }
+
+  fn remote_by_address(&self, address: u24) -> Option<&RemoteConfig> {
+    self.remotes.get(self.address_map.get(&address)?)
+  }
+
+  // The state of a remote, if a separate state file is used.
+  fn remote_state_mut(&mut self, address: u24) -> Option<&mut RemoteState> {
+    self.state_file.as_ref()?;
+    Some(self.state.entry(address).or_default())
+  }
+
+  fn check_writable(&self) -> Result<(), FileStorageError> {
+    match self.state_file {
+      Some(_) => Err(FileStorageError::ReadOnly(self.path.clone())),
+      None => Ok(()),
+    }
+  }
+
+  // Lock the file which is written, i.e. the state file if there is one.
+  fn lock(&self) -> io::Result<File> {
+    lock_file(self.state_file.as_ref().map_or(&self.path, |(state_path, _)| state_path))
+  }
+
+  // Lock the file, re-read it, apply `f` and write the result, so that changes made by
+  // other processes in the meantime are not overwritten.
+  fn update<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T, FileStorageError>) -> Result<T, FileStorageError> {
+    let _lock = self.lock()?;
+    if self.path.exists() {
+      self.reload()?;
+    }
+
+    let result = f(self)?;
+    self.save()?;
+    Ok(result)
+  }
+
+  // Read the config file and the state file, returning the version of the config file.
+  fn reload(&mut self) -> Result<u32, FileStorageError> {
+    let mut contents = String::new();
+    File::open(&self.path)?.read_to_string(&mut contents)?;
+
+    // Version 0 has no `version` field, or a remote named “version”.
+    let version = self.format.deserialize::<Version>(&contents).ok().and_then(|v| v.version).unwrap_or(0);
+
+    let Document { settings, mut remotes } = match version {
+      0 => Document { settings: Settings::default(), remotes: self.format.deserialize(&contents)? },
+      CONFIG_VERSION => self.format.deserialize(&contents)?,
+      version => return Err(FileStorageError::UnsupportedVersion(version)),
+    };
+
+    if let Some((state_path, format)) = &self.state_file {
+      self.state = read_state(state_path, *format)?;
+
+      for config in remotes.values_mut() {
+        let Some(state) = self.state.get(&config.remote.address()) else { continue };
+
+        config.remote = Remote::new(config.remote.address(), state.rolling_code).with_key(config.remote.key());
+        config.open_time = state.open_time.or(config.open_time);
+        config.close_time = state.close_time.or(config.close_time);
+        config.my_position = state.my_position.or(config.my_position);
+      }
+    }
+
+    self.address_map = remotes.iter().map(|(k, v)| (v.remote.address(), k.to_owned())).collect();
+    self.remotes = remotes;
+    self.settings = settings;
+    Ok(version)
+  }
+
+  fn save(&self) -> Result<(), FileStorageError> {
+    if let Some((state_path, format)) = &self.state_file {
+      let mut state = self.state.clone();
+      for config in self.remotes.values() {
+        state.entry(config.remote.address()).or_default().rolling_code = config.remote.rolling_code();
+      }
+
+      let remotes = state.into_iter().map(|(address, state)| (format!("{:#08x}", u32::from(address)), state)).collect();
+      let contents = format.serialize(&StateDocument { version: STATE_VERSION, remotes })?;
+      return Ok(write_file(state_path, contents.as_bytes())?)
+    }
+
+    let settings = Some(&self.settings).filter(|settings| !is_default(*settings));
+    let document = DocumentRef { version: CONFIG_VERSION, settings, remotes: &self.remotes };
+    let contents = self.format.serialize(&document)?;
+    Ok(write_file(&self.path, contents.as_bytes())?)
+  }
+}
+
+impl RollingCodeStorage for FileStorage {
+  type Error = FileStorageError;
+
+  fn persist(&mut self, remote: &Remote) -> Result<(), Self::Error> {
+    log::info!("Persisting config for remote {}.", remote.address());
+
+    let known = self.rolling_codes.insert(remote.address(), remote.rolling_code());
+
+    self.update(|storage| {
+      let Some(name) = storage.address_map.get(&remote.address()).cloned() else {
+        return Err(FileStorageError::UnknownAddress(remote.address()))
+      };
+      let config = storage.remote_config_mut(&name)?;
+
+      // If another process used rolling codes in the meantime, never move back behind them.
+      let stored = config.remote.rolling_code();
+      let behind = remote.rolling_code().wrapping_sub(stored) > u16::MAX / 2;
+      config.remote = remote.clone();
+      if known.is_some_and(|known| known != stored) && behind {
+        log::warn!("Rolling code of remote {} was advanced by another process.", remote.address());
+        config.remote.advance_rolling_code(stored.wrapping_sub(remote.rolling_code()));
+      }
+
+      Ok(())
+    })
+  }
+
+  fn reservation_size(&self) -> u16 {
+    self.reservation_size
+  }
+}
+
+// Read the state file at `path`, which is empty if it does not exist yet.
+fn read_state(path: &Path, format: FileFormat) -> Result<BTreeMap<u24, RemoteState>, FileStorageError> {
+  let contents = match fs::read_to_string(path) {
+    Ok(contents) => contents,
+    Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+    Err(err) => return Err(err.into()),
+  };
+
+  let StateDocument { version, remotes } = format.deserialize(&contents)?;
+  if version != STATE_VERSION {
+    return Err(FileStorageError::UnsupportedVersion(version))
+  }
+
+  remotes
+    .into_iter()
+    .map(|(address, state)| {
+      let parsed = address.strip_prefix("0x").and_then(|hex| u32::from_str_radix(hex, 16).ok());
+      match parsed.filter(|address| *address <= 0xFFFFFF) {
+        Some(address) => Ok((u24::new(address), state)),
+        None => {
+          let message = format!("Invalid address “{address}” in {}", path.display());
+          Err(io::Error::new(io::ErrorKind::InvalidData, message).into())
+        },
+      }
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs;
+
+  use super::*;
+
+  #[test]
+  fn test_key_strategy() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.yaml");
+
+    fs::write(&path, "Remote A:\n  address: 170\n  rolling_code: 1\n").unwrap();
+
+    let mut storage = FileStorage::open(&path).unwrap();
+    let remote = storage.remote("Remote A").unwrap().clone();
+    assert_eq!(remote.key(), KeyStrategy::default());
+
+    storage.persist(&remote.with_key(KeyStrategy::Rolling)).unwrap();
+
+    let storage = FileStorage::open(&path).unwrap();
+    assert_eq!(storage.remote("Remote A").unwrap().key(), KeyStrategy::Rolling);
+  }
+
+  #[test]
+  fn test_remote_config() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.yaml");
+
+    fs::write(
+      &path,
+      "Remote A:\n  address: 170\n  rolling_code: 1\n  key: !rolling_with_base 176\n  venetian: true\n  open_time: 20s\n  close_time: 17s 500ms\nRemote B:\n  address: 171\n  rolling_code: 2\n",
+    )
+    .unwrap();
+
+    let mut storage = FileStorage::open(&path).unwrap();
+    let config = storage.remote_config("Remote A").unwrap().clone();
+    assert!(config.is_venetian());
+    assert_eq!(config.remote.key(), KeyStrategy::RollingWithBase(176));
+    assert!(!storage.remote_config("Remote B").unwrap().is_venetian());
+    assert_eq!(config.open_time, Some(Duration::from_secs(20)));
+    assert_eq!(config.close_time, Some(Duration::from_millis(17500)));
+    assert!(config.estimator(0).is_some());
+    assert!(storage.remote_config("Remote B").unwrap().estimator(0).is_none());
+
+    storage.persist(&config.remote).unwrap();
+
+    let storage = FileStorage::open(&path).unwrap();
+    assert!(storage.remote_config("Remote A").unwrap().is_venetian());
+    assert_eq!(storage.remote("Remote A").unwrap().key(), KeyStrategy::RollingWithBase(176));
+    assert_eq!(storage.remote_config("Remote A").unwrap().close_time, Some(Duration::from_millis(17500)));
+    assert!(fs::read_to_string(&path).unwrap().contains("device: venetian_blind"));
+
+    let mut storage = FileStorage::open(&path).unwrap();
+    assert_eq!(storage.remote_config("Remote A").unwrap().my_position, None);
+    storage.set_my_position("Remote A", Some(40)).unwrap();
+    assert!(storage.set_my_position("Remote C", Some(40)).is_err());
+
+    let mut storage = FileStorage::open(&path).unwrap();
+    assert_eq!(storage.remote_config("Remote A").unwrap().my_position, Some(40));
+
+    storage.set_travel_times("Remote B", Duration::from_secs(30), Duration::from_secs(25)).unwrap();
+
+    let storage = FileStorage::open(&path).unwrap();
+    let config = storage.remote_config("Remote B").unwrap();
+    assert_eq!((config.open_time, config.close_time), (Some(Duration::from_secs(30)), Some(Duration::from_secs(25))));
+  }
+
+  #[test]
+  fn test_reserve() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.yaml");
+
+    fs::write(&path, "Remote A:\n  address: 170\n  rolling_code: 1\n").unwrap();
+
+    let mut storage = FileStorage::open(&path).unwrap();
+    storage.reserve(10);
+
+    let mut remote = storage.remote("Remote A").unwrap().clone();
+    assert_eq!(remote.reserve_rolling_code(&mut storage).unwrap(), 1);
+    assert_eq!(remote.reserve_rolling_code(&mut storage).unwrap(), 2);
+    assert_eq!(FileStorage::open(&path).unwrap().remote("Remote A").unwrap().rolling_code(), 11);
+
+    remote.commit(&mut storage).unwrap();
+    assert_eq!(FileStorage::open(&path).unwrap().remote("Remote A").unwrap().rolling_code(), 3);
+  }
+
+  #[test]
+  fn test_upgrade() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.yaml");
+
+    // A remote named “version” must not be mistaken for the version field.
+    fs::write(&path, "version:\n  address: 170\n  rolling_code: 1\n  venetian: true\n").unwrap();
+
+    let storage = FileStorage::open(&path).unwrap();
+    assert!(storage.remote_config("version").unwrap().is_venetian());
+
+    let contents = fs::read_to_string(&path).unwrap();
+    assert!(contents.starts_with("version: 1\n"));
+    assert!(contents.contains("remotes:\n  version:\n"));
+
+    fs::write(
+      &path,
+      "version: 1\nsettings:\n  transmitter_pin: 5\n  timing: somfy\nremotes:\n  Remote A:\n    address: 170\n    rolling_code: 1\n    device: awning\n    room: Terrace\n    repetitions: 4\n    transmitter_pin: 6\n",
+    )
+    .unwrap();
+
+    let storage = FileStorage::open(&path).unwrap();
+    assert_eq!(storage.settings().transmitter_pin, Some(5));
+    assert_eq!(storage.settings().timing.as_deref(), Some("somfy"));
+    let config = storage.remote_config("Remote A").unwrap();
+    assert_eq!(config.device, DeviceType::Awning);
+    assert_eq!(config.room.as_deref(), Some("Terrace"));
+    assert_eq!((config.repetitions, config.transmitter_pin), (Some(4), Some(6)));
+
+    fs::write(&path, "version: 2\nremotes: {}\n").unwrap();
+    assert!(matches!(FileStorage::open(&path), Err(FileStorageError::UnsupportedVersion(2))));
+  }
+
+  #[test]
+  fn test_formats() {
+    let dir = tempfile::tempdir().unwrap();
+
+    for name in ["config.yaml", "config.json", "config.toml"] {
+      let path = dir.path().join(name);
+
+      // The file is created if it does not exist.
+      let mut storage = FileStorage::open(&path).unwrap();
+      assert!(path.exists());
+      assert!(storage.remotes().is_empty());
+
+      storage.add_remote(String::from("Remote A"), Remote::new(u24::new(170), 1)).unwrap();
+      storage.set_travel_times("Remote A", Duration::from_secs(20), Duration::from_millis(17500)).unwrap();
+      let remote = storage.remote("Remote A").unwrap().clone().with_key(KeyStrategy::RollingWithBase(176));
+      storage.persist(&remote).unwrap();
+
+      let storage = FileStorage::open(&path).unwrap();
+      let config = storage.remote_config("Remote A").unwrap();
+      assert_eq!(config.remote.key(), KeyStrategy::RollingWithBase(176));
+      assert_eq!(config.close_time, Some(Duration::from_millis(17500)));
+    }
+
+    let path = dir.path().join("config.txt");
+    assert!(matches!(FileStorage::open(&path), Err(FileStorageError::UnsupportedFormat(_))));
+
+    let path = dir.path().join("invalid.json");
+    fs::write(&path, "{").unwrap();
+    assert!(matches!(FileStorage::open(&path), Err(FileStorageError::Json(_))));
+  }
+
+  #[test]
+  fn test_concurrent_writes() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.yaml");
+
+    fs::write(&path, "Remote A:\n  address: 170\n  rolling_code: 1\nRemote B:\n  address: 171\n  rolling_code: 1\n")
+      .unwrap();
+
+    let mut server = FileStorage::open(&path).unwrap();
+    server.reserve(10);
+    let mut cli = FileStorage::open(&path).unwrap();
+
+    let mut remote_a = server.remote("Remote A").unwrap().clone();
+    assert_eq!(remote_a.reserve_rolling_code(&mut server).unwrap(), 1);
+
+    // Changes to other remotes are kept.
+    let mut remote_b = cli.remote("Remote B").unwrap().clone();
+    remote_b.reserve_rolling_code(&mut cli).unwrap();
+    remote_a.commit(&mut server).unwrap();
+    assert_eq!(FileStorage::open(&path).unwrap().remote("Remote B").unwrap().rolling_code(), 2);
+
+    // Rolling codes used by another process are never reused.
+    let mut cli = FileStorage::open(&path).unwrap();
+    let mut other = cli.remote("Remote A").unwrap().clone();
+    assert_eq!(other.reserve_rolling_code(&mut cli).unwrap(), 2);
+    remote_a.commit(&mut server).unwrap();
+    assert_eq!(FileStorage::open(&path).unwrap().remote("Remote A").unwrap().rolling_code(), 3);
+
+    let mut remote_a = Remote::new(u24::new(170), 2);
+    server.refresh(&mut remote_a).unwrap();
+    assert_eq!(remote_a.rolling_code(), 3);
+
+    assert!(!dir.path().join("config.yaml.tmp").exists());
+  }
+
+  #[test]
+  fn test_add_remove_remote() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.yaml");
+
+    fs::write(&path, "Remote A:\n  address: 170\n  rolling_code: 1\n").unwrap();
+
+    let mut storage = FileStorage::open(&path).unwrap();
+    let address = storage.unused_address();
+    assert_ne!(address, u24::new(170));
+
+    storage.add_remote(String::from("Remote B"), Remote::new(address, 0)).unwrap();
+    assert!(storage.add_remote(String::from("Remote B"), Remote::new(u24::new(1), 0)).is_err());
+    assert!(storage.add_remote(String::from("Remote C"), Remote::new(u24::new(170), 0)).is_err());
+
+    let mut storage = FileStorage::open(&path).unwrap();
+    assert_eq!(storage.remote_name(address), Some("Remote B"));
+
+    assert!(storage.remove_remote("Remote A").unwrap().is_some());
+    assert!(storage.remove_remote("Remote A").unwrap().is_none());
+
+    let storage = FileStorage::open(&path).unwrap();
+    assert!(storage.remote("Remote A").is_none());
+    assert_eq!(storage.remote_name(u24::new(170)), None);
+  }
+
+  #[test]
+  fn test_storage() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.yaml");
+
+    let mut storage = FileStorage::open(&path).unwrap();
+    storage.add_remote(String::from("Remote A"), Remote::new(u24::new(0xAA), 0xA7)).unwrap();
+    storage.add_remote(String::from("Remote B"), Remote::new(u24::new(0xAF), 0xA7)).unwrap();
+
+    storage.rename_remote("Remote A", String::from("Remote C")).unwrap();
+    assert!(matches!(
+      storage.rename_remote("Remote B", String::from("Remote C")),
+      Err(FileStorageError::DuplicateName(_))
+    ));
+    assert!(matches!(
+      storage.rename_remote("Remote A", String::from("Remote D")),
+      Err(FileStorageError::UnknownRemote(_))
+    ));
+
+    storage.set_address("Remote C", u24::new(0xAB)).unwrap();
+    assert!(matches!(storage.set_address("Remote C", u24::new(0xAF)), Err(FileStorageError::DuplicateAddress(_))));
+    storage.set_rolling_code("Remote B", 42).unwrap();
+
+    let mut storage = FileStorage::open(&path).unwrap();
+    assert!(storage.remote("Remote A").is_none());
+    assert_eq!(storage.remote("Remote C").unwrap().address(), u24::new(0xAB));
+    assert_eq!(storage.remote_name(u24::new(0xAB)), Some("Remote C"));
+    assert_eq!(storage.remote_name(u24::new(0xAA)), None);
+    assert_eq!(storage.remote("Remote B").unwrap().rolling_code(), 42);
+
+    storage.remove_remote("Remote B").unwrap();
+    storage.remove_remote("Remote C").unwrap();
+    assert!(FileStorage::open(&path).unwrap().remotes().is_empty());
+  }
+
+  #[test]
+  fn test_state_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.yaml");
+    let state_path = dir.path().join("state").join("rolling_codes.yaml");
+    fs::create_dir(state_path.parent().unwrap()).unwrap();
+
+    let config = "Remote A:\n  address: 170\n  rolling_code: 1\n  my_position: 20\n";
+    fs::write(&path, config).unwrap();
+
+    let mut storage = FileStorage::open_with_state(&path, &state_path).unwrap();
+    let mut remote = storage.remote("Remote A").unwrap().clone();
+    remote.reserve_rolling_code(&mut storage).unwrap();
+    storage.set_travel_times("Remote A", Duration::from_secs(20), Duration::from_secs(18)).unwrap();
+    assert!(matches!(storage.rename_remote("Remote A", String::from("Remote B")), Err(FileStorageError::ReadOnly(_))));
+
+    // The config file is never written, even if it has an old version.
+    assert_eq!(fs::read_to_string(&path).unwrap(), config);
+
+    let contents = fs::read_to_string(&state_path).unwrap();
+    assert!(contents.contains("'0x0000aa':\n    rolling_code: 2\n"));
+
+    // The state is kept when renaming a remote in the config file.
+    fs::write(&path, "version: 1\nremotes:\n  Remote B:\n    address: 170\n    rolling_code: 1\n").unwrap();
+    let storage = FileStorage::open_with_state(&path, &state_path).unwrap();
+    let config = storage.remote_config("Remote B").unwrap();
+    assert_eq!(config.remote.rolling_code(), 2);
+    assert_eq!(config.open_time, Some(Duration::from_secs(20)));
+    assert_eq!(config.my_position, None);
+
+    // The config file is not created if it is missing.
+    assert!(FileStorage::open_with_state(dir.path().join("missing.yaml"), &state_path).is_err());
+  }
+}
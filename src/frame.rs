@@ -1,8 +1,36 @@
+use core::fmt;
+
 use ux::u24;
 
-use crate::Command;
+use crate::{Command, TimingProfile};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+  /// The checksum nibble does not match the frame contents.
+  InvalidChecksum { expected: u8, actual: u8 },
+  /// The command nibble does not correspond to a known `Command`.
+  UnknownCommand(u8),
+}
+
+impl fmt::Display for FrameError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::InvalidChecksum { expected, actual } => {
+        write!(f, "Invalid checksum: expected {expected:#03X}, got {actual:#03X}")
+      },
+      Self::UnknownCommand(command) => write!(f, "Unknown command {command:#03X}"),
+    }
+  }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FrameError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    None
+  }
+}
 
-#[derive(Default, Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
 pub struct Frame {
   key: u8,
@@ -16,16 +44,95 @@ impl Frame {
     FrameBuilder::new()
   }
 
+  /// Parse an obfuscated frame, e.g. as received over the air.
+  ///
+  /// The frame is deobfuscated and its checksum and command are verified.
+  pub fn from_bytes(bytes: [u8; 7]) -> Result<Self, FrameError> {
+    let frame = Self {
+      key: bytes[0],
+      command_and_checksum: bytes[1],
+      rolling_code: [bytes[2], bytes[3]],
+      remote_address: [bytes[4], bytes[5], bytes[6]],
+    };
+
+    let decoded = frame.deobfuscated();
+
+    let expected = decoded.calculate_checksum();
+    let actual = decoded.command_and_checksum & 0b1111;
+    if expected != actual {
+      return Err(FrameError::InvalidChecksum { expected, actual })
+    }
+
+    let command = decoded.command_and_checksum & 0b11110000;
+    if Command::try_from(command).is_err() {
+      return Err(FrameError::UnknownCommand(command >> 4))
+    }
+
+    Ok(frame)
+  }
+
+  /// Create a frame from obfuscated bytes without verifying it, e.g. to send invalid frames in tests.
+  #[cfg(test)]
+  pub(crate) fn from_bytes_unchecked(bytes: [u8; 7]) -> Self {
+    Self {
+      key: bytes[0],
+      command_and_checksum: bytes[1],
+      rolling_code: [bytes[2], bytes[3]],
+      remote_address: [bytes[4], bytes[5], bytes[6]],
+    }
+  }
+
+  pub fn key(&self) -> u8 {
+    self.key
+  }
+
+  pub fn command(&self) -> Command {
+    let command = self.deobfuscated().command_and_checksum & 0b11110000;
+
+    // A `Frame` can only be constructed with a valid command.
+    Command::try_from(command).expect("invalid command")
+  }
+
+  pub fn checksum(&self) -> u8 {
+    self.deobfuscated().command_and_checksum & 0b1111
+  }
+
   pub fn rolling_code(&self) -> u16 {
-    u16::from_be_bytes(self.rolling_code)
+    u16::from_be_bytes(self.deobfuscated().rolling_code)
+  }
+
+  pub fn remote_address(&self) -> u24 {
+    let [a, b, c] = self.deobfuscated().remote_address;
+    u24::new(u32::from_le_bytes([a, b, c, 0]))
   }
 
-  pub(crate) fn as_bytes(&self) -> &[u8] {
+  /// The obfuscated frame, as sent over the air.
+  pub fn as_bytes(&self) -> &[u8; 7] {
     // SAFETY: This is safe because a `Frame` is always
     // exactly 7 bytes containing valid data.
     unsafe { core::mem::transmute::<&Frame, &[u8; 7]>(self) }
   }
 
+  // Calculate the checksum of a deobfuscated message by XOR'ing all nibbles except the checksum itself.
+  fn calculate_checksum(&self) -> u8 {
+    let command = self.command_and_checksum & 0b11110000;
+
+    (self.key >> 4
+      ^ self.key
+      ^ command >> 4
+      ^ self.rolling_code[0] >> 4
+      ^ self.rolling_code[0]
+      ^ self.rolling_code[1] >> 4
+      ^ self.rolling_code[1]
+      ^ self.remote_address[0] >> 4
+      ^ self.remote_address[0]
+      ^ self.remote_address[1] >> 4
+      ^ self.remote_address[1]
+      ^ self.remote_address[2] >> 4
+      ^ self.remote_address[2])
+      & 0b1111
+  }
+
   // Obfuscate the message by XOR'ing all bytes.
   fn obfuscate(&mut self) {
     self.command_and_checksum ^= self.key;
@@ -37,7 +144,6 @@ impl Frame {
   }
 
   // Deobfuscate the message by XOR'ing all bytes in reverse order.
-  #[cfg(test)]
   fn deobfuscate(&mut self) {
     self.remote_address[2] ^= self.remote_address[1];
     self.remote_address[1] ^= self.remote_address[0];
@@ -46,6 +152,20 @@ impl Frame {
     self.rolling_code[0] ^= self.command_and_checksum;
     self.command_and_checksum ^= self.key;
   }
+
+  fn deobfuscated(&self) -> Self {
+    let mut frame = *self;
+    frame.deobfuscate();
+    frame
+  }
+}
+
+impl TryFrom<[u8; 7]> for Frame {
+  type Error = FrameError;
+
+  fn try_from(bytes: [u8; 7]) -> Result<Self, Self::Error> {
+    Self::from_bytes(bytes)
+  }
 }
 
 #[derive(Default, Debug, Clone)]
@@ -88,23 +208,8 @@ impl FrameBuilder {
     let remote_address = u32::from(self.remote_address?).to_le_bytes();
     let remote_address = [remote_address[0], remote_address[1], remote_address[2]];
 
-    // Calculate the checksum by XOR'ing all nibbles.
-    let checksum = (key >> 4
-      ^ key
-      ^ command >> 4
-      ^ rolling_code[0] >> 4
-      ^ rolling_code[0]
-      ^ rolling_code[1] >> 4
-      ^ rolling_code[1]
-      ^ remote_address[0] >> 4
-      ^ remote_address[0]
-      ^ remote_address[1] >> 4
-      ^ remote_address[1]
-      ^ remote_address[2] >> 4
-      ^ remote_address[2])
-      & 0b1111;
-
-    let mut frame = Frame { key, command_and_checksum: command | checksum, rolling_code, remote_address };
+    let mut frame = Frame { key, command_and_checksum: command, rolling_code, remote_address };
+    frame.command_and_checksum |= frame.calculate_checksum();
     frame.obfuscate();
 
     Some(frame)
@@ -114,6 +219,11 @@ impl FrameBuilder {
 pub trait SendFrame {
   type Error;
 
+  /// The `TimingProfile` used for sending frames.
+  fn timing(&self) -> TimingProfile {
+    TimingProfile::SOMFY
+  }
+
   fn send_frame(&mut self, frame: &Frame) -> Result<(), Self::Error> {
     self.send_frame_repeat(frame, 0)
   }
@@ -121,6 +231,23 @@ pub trait SendFrame {
   fn send_frame_repeat(&mut self, frame: &Frame, repetitions: usize) -> Result<(), Self::Error>;
 }
 
+#[cfg(feature = "async")]
+#[allow(async_fn_in_trait)]
+pub trait AsyncSendFrame {
+  type Error;
+
+  /// The `TimingProfile` used for sending frames.
+  fn timing(&self) -> TimingProfile {
+    TimingProfile::SOMFY
+  }
+
+  async fn send_frame(&mut self, frame: &Frame) -> Result<(), Self::Error> {
+    self.send_frame_repeat(frame, 0).await
+  }
+
+  async fn send_frame_repeat(&mut self, frame: &Frame, repetitions: usize) -> Result<(), Self::Error>;
+}
+
 #[cfg(test)]
 mod tests {
   use ux::u24;
@@ -158,4 +285,48 @@ mod tests {
       remote_address
     );
   }
+
+  #[test]
+  fn test_frame_from_bytes() {
+    let frame = Frame::builder()
+      .key(0xA7)
+      .command(Command::Down)
+      .rolling_code(0x1234)
+      .remote_address(u24::new(0xFFAA11))
+      .build()
+      .expect("Failed to build frame");
+
+    let decoded = Frame::from_bytes(*frame.as_bytes()).expect("Failed to decode frame");
+
+    assert_eq!(decoded, frame);
+    assert_eq!(decoded.key(), 0xA7);
+    assert_eq!(decoded.command(), Command::Down);
+    assert_eq!(decoded.rolling_code(), 0x1234);
+    assert_eq!(decoded.remote_address(), u24::new(0xFFAA11));
+  }
+
+  #[test]
+  fn test_frame_from_bytes_invalid_checksum() {
+    let frame = Frame::builder()
+      .key(0xA7)
+      .command(Command::Up)
+      .rolling_code(42)
+      .remote_address(u24::new(0xFFAA11))
+      .build()
+      .expect("Failed to build frame");
+
+    let mut bytes = *frame.as_bytes();
+    bytes[6] ^= 0b0001;
+
+    assert!(matches!(Frame::try_from(bytes), Err(FrameError::InvalidChecksum { .. })));
+  }
+
+  #[test]
+  fn test_frame_from_bytes_unknown_command() {
+    let mut frame = Frame { key: 0xA7, command_and_checksum: 0xF0, rolling_code: [0, 42], remote_address: [1, 2, 3] };
+    frame.command_and_checksum |= frame.calculate_checksum();
+    frame.obfuscate();
+
+    assert_eq!(Frame::from_bytes(*frame.as_bytes()), Err(FrameError::UnknownCommand(0xF)));
+  }
 }
@@ -0,0 +1,45 @@
+/// Strategy for choosing the encryption key of a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+pub enum KeyStrategy {
+  /// Always use the same key.
+  Fixed(u8),
+  /// Cycle the low nibble of the key with the rolling code, i.e. `0xA0 | (rolling_code & 0x0F)`,
+  /// like original Somfy remotes.
+  Rolling,
+  /// Cycle the low nibble of the key with the rolling code, using the high nibble of the given base.
+  RollingWithBase(u8),
+}
+
+impl KeyStrategy {
+  /// The key to use for a frame with the given `rolling_code`.
+  pub fn key(&self, rolling_code: u16) -> u8 {
+    let low_nibble = (rolling_code & 0x0F) as u8;
+
+    match *self {
+      Self::Fixed(key) => key,
+      Self::Rolling => 0xA0 | low_nibble,
+      Self::RollingWithBase(base) => (base & 0xF0) | low_nibble,
+    }
+  }
+}
+
+impl Default for KeyStrategy {
+  fn default() -> Self {
+    Self::Fixed(0xA7)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_key() {
+    assert_eq!(KeyStrategy::default().key(0x1234), 0xA7);
+    assert_eq!(KeyStrategy::Fixed(0xB3).key(0x1234), 0xB3);
+    assert_eq!(KeyStrategy::Rolling.key(0x1234), 0xA4);
+    assert_eq!(KeyStrategy::Rolling.key(0x123F), 0xAF);
+    assert_eq!(KeyStrategy::RollingWithBase(0xC7).key(0x1235), 0xC5);
+  }
+}
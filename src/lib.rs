@@ -1,10 +1,37 @@
-use std::fmt;
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+use core::fmt;
+
+mod calibration;
+pub use calibration::{Calibration, CalibrationStep};
 
 mod command;
 pub use command::{Command, UnknownCommand};
 
+mod direction;
+pub use direction::{Direction, UnknownDirection};
+
 mod frame;
-pub use frame::{Frame, SendFrame};
+#[cfg(feature = "async")]
+pub use frame::AsyncSendFrame;
+pub use frame::{Frame, FrameError, SendFrame};
+
+mod key;
+pub use key::KeyStrategy;
+
+mod timing;
+pub use timing::{TimingProfile, UnknownTimingProfile};
+
+mod demodulator;
+pub use demodulator::{Demodulator, Tolerance};
+
+mod position;
+pub use position::{Motion, PositionEstimator};
+
+mod receiver;
+pub use receiver::{ButtonPress, Receiver};
 
 mod sender;
 pub use sender::Sender;
@@ -12,9 +39,28 @@ pub use sender::Sender;
 mod spi_sender;
 pub use spi_sender::SpiSender;
 
+mod waveform;
+pub use waveform::{Pulse, Waveform};
+
 mod remote;
 pub use remote::Remote;
 
+#[cfg(any(feature = "testing", test))]
+pub mod testing;
+
+#[cfg(feature = "file-storage")]
+pub mod file_io;
+
+#[cfg(feature = "file-storage")]
+mod file_storage;
+#[cfg(feature = "file-storage")]
+pub use file_storage::{DeviceType, FileFormat, FileStorage, FileStorageError, RemoteConfig, Settings, CONFIG_VERSION};
+
+#[cfg(feature = "sqlite")]
+mod sqlite_storage;
+#[cfg(feature = "sqlite")]
+pub use sqlite_storage::{SqliteStorage, SqliteStorageError, Transmission};
+
 pub enum Error<T, S> {
   TransmitError(T),
   StorageError(S),
@@ -54,6 +100,7 @@ where
   }
 }
 
+#[cfg(feature = "std")]
 impl<T, S> std::error::Error for Error<T, S>
 where
   T: std::error::Error + 'static,
@@ -70,5 +117,38 @@ where
 pub trait RollingCodeStorage {
   type Error;
 
+  /// Persist the state of the given `remote`, most importantly its next unused rolling code.
   fn persist(&mut self, remote: &Remote) -> Result<(), Self::Error>;
+
+  /// The number of rolling codes to reserve with a single write.
+  ///
+  /// Reserving blocks of rolling codes reduces the number of writes, at the cost of skipping
+  /// the unused codes of a block if the remote is not committed using `Remote::commit`.
+  fn reservation_size(&self) -> u16 {
+    1
+  }
+
+  /// Record that `frame` was transmitted with the given number of `repetitions`, e.g. to keep a history.
+  ///
+  /// This is called after every transmission, with `sent` indicating whether it succeeded.
+  /// Errors are logged, but do not fail the transmission.
+  fn record_transmission(&mut self, frame: &Frame, repetitions: usize, sent: bool) -> Result<(), Self::Error> {
+    let _ = (frame, repetitions, sent);
+    Ok(())
+  }
+}
+
+/// Generate a random, non-zero address for which `is_used` returns `false`.
+#[cfg(feature = "file-storage")]
+pub(crate) fn unused_address(is_used: impl Fn(ux::u24) -> bool) -> ux::u24 {
+  use std::hash::{BuildHasher, RandomState};
+
+  use ux::u24;
+
+  let random_state = RandomState::new();
+
+  (0u64..)
+    .map(|i| u24::new((random_state.hash_one(i) & 0xFFFFFF) as u32))
+    .find(|address| *address != u24::new(0) && !is_used(*address))
+    .unwrap()
 }
@@ -1,10 +1,24 @@
-use std::{error::Error, path::PathBuf, process::exit};
+use std::{
+  error::Error,
+  fs,
+  io::{self, Write},
+  path::PathBuf,
+  process::exit,
+  time::{Duration, Instant},
+};
 
-use clap::{arg, value_parser, ArgAction, Command};
-
-use rppal::{gpio::Gpio, hal::Delay};
+use clap::{arg, value_parser, ArgAction, ArgGroup, ArgMatches, Command};
+use embedded_hal::digital::PinState;
+use rppal::{
+  gpio::{Gpio, Level, Trigger},
+  hal::Delay,
+};
 
 use somfy::*;
+use ux::u24;
+
+mod state;
+use state::StateFile;
 
 mod storage;
 use storage::Storage;
@@ -16,8 +30,15 @@ mod thing;
 use webthing::{Thing, ThingsType, WebThingServer};
 
 const TRANSMITTER_PIN: u8 = 4;
+const RECEIVER_PIN: u8 = 17;
+
+const RECEIVER_POLL_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Duration of a long Prog press for pairing and unpairing.
+const PROG_HOLD: Duration = Duration::from_secs(2);
 
 const DEFAULT_CONFIG_FILE_PATH: &str = "./config.yaml";
+const DEFAULT_STATE_FILE_NAME: &str = "state.yaml";
 
 #[actix_rt::main]
 async fn main() -> Result<(), Box<dyn Error>> {
@@ -30,54 +51,291 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .action(ArgAction::Set)
         .value_parser(value_parser!(PathBuf)),
     )
+    .arg(
+      arg!(--storage <URL> "Where to store remotes, either a config file path or “sqlite://PATH” [default: the config file]")
+        .action(ArgAction::Set),
+    )
+    .arg(
+      arg!(--state <FILE> "Path to the state file [default: “state.yaml” in the state directory or next to the config file]")
+        .action(ArgAction::Set)
+        .value_parser(value_parser!(PathBuf)),
+    )
+    .arg(
+      arg!(--"state-dir" <DIR> "Directory for rolling codes and other state, e.g. “/var/lib/somfy”, which keeps the config file read-only")
+        .action(ArgAction::Set)
+        .value_parser(value_parser!(PathBuf)),
+    )
+    .arg(
+      arg!(--timing <PROFILE> "Timing profile used for sending and receiving [default: from the config file or “somfy”]")
+        .action(ArgAction::Set)
+        .value_parser(|s: &str| s.parse::<TimingProfile>().map_err(|err| err.to_string())),
+    )
+    .arg(
+      arg!(--"timing-correction" <MICROSECONDS> "Correction added to high pulses and subtracted from low pulses [default: from the config file or 0]")
+        .allow_negative_numbers(true)
+        .action(ArgAction::Set)
+        .value_parser(value_parser!(i32)),
+    )
     .subcommands(["my", "up", "myup", "down", "mydown", "updown", "myupdown", "prog", "sunflag", "flag"].map(
       |command| {
         Command::new(command)
           .about(format!("Send the {command} command"))
           .arg(arg!(<remote> "The remote name").action(ArgAction::Set))
           .arg(
-            arg!(-r --repetitions <count> "Number of command repetitions")
+            arg!(-r --repetitions <count> "Number of command repetitions [default: from the config file or 0]")
               .value_parser(value_parser!(usize))
-              .default_value("0")
               .action(ArgAction::Set),
           )
+          .arg(
+            arg!(--hold <DURATION> "Duration to hold the button, e.g. “2s”")
+              .conflicts_with("repetitions")
+              .action(ArgAction::Set)
+              .value_parser(|s: &str| humantime::parse_duration(s).map_err(|err| err.to_string())),
+          )
       },
     ))
-    .subcommand(Command::new("server").long_flag("server").short_flag('s').about("Start API server"))
+    .subcommand(
+      Command::new("calibrate")
+        .about("Measure the travel times of a blind")
+        .arg(arg!(<remote> "The remote name").action(ArgAction::Set)),
+    )
+    .subcommand(
+      Command::new("store-my")
+        .about("Store the current position of a blind as its favourite position")
+        .arg(arg!(<remote> "The remote name").action(ArgAction::Set))
+        .arg(
+          arg!(-p --position <PERCENT> "The current position [default: the estimated position]")
+            .action(ArgAction::Set)
+            .value_parser(value_parser!(u8).range(0..=100)),
+        ),
+    )
+    .subcommand(
+      Command::new("tilt")
+        .about("Tilt the slats of a venetian blind")
+        .arg(arg!(<remote> "The remote name").action(ArgAction::Set))
+        .arg(
+          arg!(<direction> "The direction, “up” or “down”")
+            .action(ArgAction::Set)
+            .value_parser(|s: &str| s.parse::<Direction>().map_err(|err| err.to_string())),
+        )
+        .arg(
+          arg!(-s --steps <COUNT> "Number of tilt steps")
+            .default_value("1")
+            .action(ArgAction::Set)
+            .value_parser(value_parser!(usize)),
+        ),
+    )
+    .subcommand(
+      Command::new("pair")
+        .about("Add a new remote with a random address and pair it with a motor")
+        .arg(arg!(<remote> "The remote name").action(ArgAction::Set)),
+    )
+    .subcommand(
+      Command::new("unpair")
+        .about("Unpair a remote from a motor")
+        .arg(arg!(<remote> "The remote name").action(ArgAction::Set))
+        .arg(arg!(--remove "Also remove the remote from the config file").action(ArgAction::SetTrue)),
+    )
+    .subcommand(
+      Command::new("resync")
+        .about("Resynchronise the rolling code of a remote with a motor which no longer reacts")
+        .arg(arg!(<remote> "The remote name").action(ArgAction::Set))
+        .arg(
+          arg!(-c --command <COMMAND> "Command sent to check whether the motor reacts")
+            .default_value("up")
+            .action(ArgAction::Set)
+            .value_parser(|s: &str| s.parse::<somfy::Command>().map_err(|err| err.to_string())),
+        )
+        .arg(
+          arg!(--step <COUNT> "Number of rolling codes to advance between attempts")
+            .default_value("50")
+            .action(ArgAction::Set)
+            .value_parser(value_parser!(u16).range(1..)),
+        )
+        .arg(
+          arg!(--max <COUNT> "Maximum number of rolling codes to advance")
+            .default_value("5000")
+            .action(ArgAction::Set)
+            .value_parser(value_parser!(u16)),
+        ),
+    )
+    .subcommand(
+      Command::new("remote")
+        .about("Manage remotes without sending any commands")
+        .subcommand_required(true)
+        .subcommand(
+          Command::new("add")
+            .about("Add a remote, e.g. to take over an existing one")
+            .arg(arg!(<remote> "The remote name").action(ArgAction::Set))
+            .arg(
+              arg!(-a --address <ADDRESS> "The address, decimal or hexadecimal with “0x” prefix [default: random]")
+                .action(ArgAction::Set)
+                .value_parser(parse_address),
+            )
+            .arg(
+              arg!(-c --"rolling-code" <CODE> "The next unused rolling code")
+                .default_value("0")
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(u16)),
+            ),
+        )
+        .subcommand(
+          Command::new("rm")
+            .about("Remove a remote without unpairing it")
+            .arg(arg!(<remote> "The remote name").action(ArgAction::Set)),
+        )
+        .subcommand(
+          Command::new("mv")
+            .about("Rename a remote")
+            .arg(arg!(<remote> "The remote name").action(ArgAction::Set))
+            .arg(arg!(<new_name> "The new name").action(ArgAction::Set)),
+        )
+        .subcommand(
+          Command::new("set")
+            .about("Change the address or rolling code of a remote")
+            .arg(arg!(<remote> "The remote name").action(ArgAction::Set))
+            .arg(
+              arg!(-a --address <ADDRESS> "The new address, decimal or hexadecimal with “0x” prefix")
+                .action(ArgAction::Set)
+                .value_parser(parse_address),
+            )
+            .arg(
+              arg!(-c --"rolling-code" <CODE> "The next unused rolling code")
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(u16)),
+            )
+            .group(ArgGroup::new("changes").args(["address", "rolling-code"]).required(true).multiple(true)),
+        )
+        .subcommand(Command::new("list").about("List all remotes")),
+    )
+    .subcommand(Command::new("receive").about("Receive and print frames sent by remotes"))
+    .subcommand(
+      Command::new("server")
+        .long_flag("server")
+        .short_flag('s')
+        .about("Start API server")
+        .arg(
+          arg!(--reserve <COUNT> "Number of rolling codes to reserve with a single write to the config file")
+            .value_parser(value_parser!(u16).range(1..))
+            .default_value("1")
+            .action(ArgAction::Set),
+        )
+        .arg(
+          arg!(--rehome <INTERVAL> "Periodically move blinds with known travel times to an end stop and back, e.g. “24h”")
+            .action(ArgAction::Set)
+            .value_parser(|s: &str| humantime::parse_duration(s).map_err(|err| err.to_string())),
+        ),
+    )
     .get_matches();
 
+  let config_path: &PathBuf = matches.get_one("config").unwrap();
+  let state_dir = matches.get_one::<PathBuf>("state-dir").map(|state_dir| state_dir.as_path());
+  if let Some(state_dir) = state_dir {
+    fs::create_dir_all(state_dir)?;
+  }
+
+  let url = matches.get_one::<String>("storage").map(|url| url.as_str());
+  let mut storage = Storage::open(url, config_path, state_dir)?;
+
+  let state_path = match (matches.get_one::<PathBuf>("state"), state_dir) {
+    (Some(state_path), _) => state_path.clone(),
+    (None, Some(state_dir)) => state_dir.join(DEFAULT_STATE_FILE_NAME),
+    (None, None) => config_path.with_file_name(DEFAULT_STATE_FILE_NAME),
+  };
+  let mut state = StateFile::open(state_path)?;
+
+  // Managing remotes does not need any hardware.
+  if let Some(("remote", matches)) = matches.subcommand() {
+    return manage_remotes(matches, &mut storage, &mut state)
+  }
+
+  let settings = storage.settings(config_path, state_dir)?;
+
+  let timing = match (matches.get_one::<TimingProfile>("timing"), &settings.timing) {
+    (Some(timing), _) => *timing,
+    (None, Some(timing)) => timing.parse::<TimingProfile>()?,
+    (None, None) => TimingProfile::SOMFY,
+  };
+  let timing_correction: i32 =
+    matches.get_one("timing-correction").copied().or(settings.timing_correction).unwrap_or_default();
+
   let gpio = Gpio::new()?;
 
-  let mut transmitter = gpio.get(TRANSMITTER_PIN)?.into_output();
-  transmitter.set_low();
+  let default_transmitter_pin = settings.transmitter_pin.unwrap_or(TRANSMITTER_PIN);
+  let new_sender = |pin: u8| -> Result<_, rppal::gpio::Error> {
+    let mut transmitter = gpio.get(pin)?.into_output();
+    transmitter.set_low();
 
-  let mut sender = Sender { transmitter, delay: Delay };
+    Ok(Sender { transmitter, delay: Delay, timing: timing.with_correction(timing_correction) })
+  };
 
-  // let mut sender = SpiSender::new();
+  // Remotes can be assigned to a different transmitter than the default one.
+  let remote_config = matches
+    .subcommand()
+    .and_then(|(_, matches)| matches.try_get_one::<String>("remote").ok().flatten())
+    .and_then(|remote_name| storage.remote_config(remote_name));
+  let transmitter_pin = remote_config.and_then(|config| config.transmitter_pin).unwrap_or(default_transmitter_pin);
 
-  let storage_path: &PathBuf = matches.get_one("config").unwrap();
-  let mut storage = Storage::new(storage_path)?;
+  let mut sender = new_sender(transmitter_pin)?;
 
   match matches.subcommand_name() {
     #[cfg(feature = "server")]
     Some("server") => {
       use std::{
-        collections::HashMap,
+        collections::{hash_map::Entry, HashMap},
         sync::{Arc, Mutex, RwLock},
       };
 
-      let mut remotes = HashMap::new();
+      let server_matches = matches.subcommand_matches("server").unwrap();
+      let reservation_size: u16 = server_matches.get_one("reserve").copied().unwrap();
+      storage.reserve(reservation_size);
 
+      let rehome_interval: Option<Duration> = server_matches.get_one("rehome").copied();
+
+      let configs = storage.remotes().clone();
+      let mut senders = HashMap::from([(transmitter_pin, Arc::new(Mutex::new(sender)))]);
+      let storage = Arc::new(Mutex::new(storage));
+      let state = Arc::new(Mutex::new(state));
+
+      let mut blinds = HashMap::new();
       let mut things = Vec::<Arc<RwLock<Box<dyn Thing + 'static>>>>::new();
+      let mut rehome_blinds = Vec::new();
+
+      for (name, config) in configs {
+        let position = state.lock().unwrap().position(&name, &config).unwrap_or(thing::INITIAL_POSITION);
+        let thing: Arc<RwLock<Box<dyn Thing + 'static>>> =
+          Arc::new(RwLock::new(Box::new(thing::make_remote(&name, &config, position))));
+
+        let pin = config.transmitter_pin.unwrap_or(default_transmitter_pin);
+        let sender = match senders.entry(pin) {
+          Entry::Occupied(entry) => entry.get().clone(),
+          Entry::Vacant(entry) => entry.insert(Arc::new(Mutex::new(new_sender(pin)?))).clone(),
+        };
+
+        let blind = thing::Blind {
+          name,
+          venetian: config.is_venetian(),
+          repetitions: config.repetitions.unwrap_or(thing::DEFAULT_REPETITIONS),
+          sender,
+          storage: storage.clone(),
+          state: state.clone(),
+          remote: Arc::new(RwLock::new(config.remote.clone())),
+          estimator: Arc::new(Mutex::new(config.estimator(position))),
+          calibration: Arc::new(Mutex::new(None)),
+        };
 
-      for (name, remote) in storage.remotes() {
-        let thing = thing::make_remote(name, remote);
-        remotes.insert(thing.get_id().clone(), Arc::new(RwLock::new(remote.clone())));
-        things.push(Arc::new(RwLock::new(Box::new(thing))));
+        blinds.insert(thing.read().unwrap().get_id(), blind.clone());
+        rehome_blinds.push((blind, thing.clone()));
+        things.push(thing);
       }
 
-      let generator =
-        thing::Generator { sender: Arc::new(Mutex::new(sender)), storage: Arc::new(RwLock::new(storage)), remotes };
+      let remotes = blinds.values().map(|blind| blind.remote.clone()).collect::<Vec<_>>();
+
+      if let Some(interval) = rehome_interval {
+        thing::rehome(rehome_blinds, interval);
+      }
+
+      let generator = thing::Generator { blinds };
 
       log::info!("Starting server.");
       let mut server = WebThingServer::new(
@@ -91,18 +349,212 @@ async fn main() -> Result<(), Box<dyn Error>> {
       );
       server.start(None).await?;
 
+      // Release reserved but unused rolling codes.
+      let mut storage = storage.lock().unwrap();
+      for remote in remotes {
+        remote.write().unwrap().commit(&mut *storage)?;
+      }
+
       return Ok(())
     },
+    Some("receive") => receive(&gpio, settings.receiver_pin.unwrap_or(RECEIVER_PIN), &storage, timing)?,
+    Some("calibrate") => {
+      let matches = matches.subcommand_matches("calibrate").unwrap();
+      let remote_name: &String = matches.get_one("remote").unwrap();
+
+      let Some(config) = storage.remote_config(remote_name) else {
+        eprintln!("No remote with name “{remote_name}” found.");
+        exit(1);
+      };
+
+      let mut remote = config.remote.clone();
+      // Short presses only tilt the slats of venetian blinds.
+      let hold = if config.is_venetian() { Direction::TRAVEL_HOLD } else { Duration::ZERO };
+
+      let start = Instant::now();
+      let (mut calibration, mut direction) = Calibration::start();
+
+      loop {
+        remote.send_hold(&mut sender, &mut storage, direction.command(), hold)?;
+
+        let end_stop = match direction {
+          Direction::Up => "upper",
+          Direction::Down => "lower",
+        };
+        prompt(&format!(
+          "Moving “{remote_name}” {direction:?}, press Enter as soon as it reaches the {end_stop} end stop."
+        ));
+
+        match calibration.end_stop(start.elapsed()) {
+          CalibrationStep::Move(next_direction) => direction = next_direction,
+          CalibrationStep::Done { open_time, close_time } => {
+            storage.set_travel_times(remote_name, open_time, close_time)?;
+            state.record(remote_name, Some(0), None, Direction::Down.command())?;
+
+            let config = storage.remote_config(remote_name).unwrap();
+            println!(
+              "Measured an open time of {} and a close time of {} for “{remote_name}”.",
+              humantime::format_duration(config.open_time.unwrap()),
+              humantime::format_duration(config.close_time.unwrap()),
+            );
+            break
+          },
+        }
+      }
+    },
+    Some("store-my") => {
+      let matches = matches.subcommand_matches("store-my").unwrap();
+      let remote_name: &String = matches.get_one("remote").unwrap();
+
+      let Some(config) = storage.remote_config(remote_name) else {
+        eprintln!("No remote with name “{remote_name}” found.");
+        exit(1);
+      };
+
+      let Some(position) = matches.get_one("position").copied().or_else(|| state.position(remote_name, config)) else {
+        eprintln!("The position of “{remote_name}” is unknown, specify it using “--position”.");
+        exit(1);
+      };
+
+      let mut remote = config.remote.clone();
+
+      log::info!("Storing favourite position {position} % with remote “{remote_name}”.");
+      remote.store_my_position(&mut sender, &mut storage)?;
+      storage.set_my_position(remote_name, Some(position))?;
+
+      println!("Stored favourite position {position} % for “{remote_name}”.");
+    },
+    Some("tilt") => {
+      let matches = matches.subcommand_matches("tilt").unwrap();
+
+      let remote_name: &String = matches.get_one("remote").unwrap();
+      let direction: Direction = matches.get_one("direction").copied().unwrap();
+      let steps: usize = matches.get_one("steps").copied().unwrap();
+
+      let Some(config) = storage.remote_config(remote_name) else {
+        eprintln!("No remote with name “{remote_name}” found.");
+        exit(1);
+      };
+
+      if !config.is_venetian() {
+        eprintln!("Remote “{remote_name}” is not configured as venetian.");
+        exit(1);
+      }
+
+      log::info!("Tilting {direction:?} by {steps} steps with remote “{remote_name}”.");
+      config.remote.clone().tilt_step(&mut sender, &mut storage, direction, steps)?;
+    },
+    Some("pair") => {
+      let matches = matches.subcommand_matches("pair").unwrap();
+      let remote_name: &String = matches.get_one("remote").unwrap();
+
+      if storage.remote(remote_name).is_some() {
+        eprintln!("A remote with name “{remote_name}” already exists.");
+        exit(1);
+      }
+
+      let mut remote = Remote::new(storage.unused_address(), 0);
+      storage.add_remote(remote_name.clone(), remote.clone())?;
+      println!("Added remote “{remote_name}” with address {}.", remote.address());
+
+      prompt(
+        "Hold the Prog button of an existing remote of the blind until it moves up and down briefly, then press Enter.",
+      );
+
+      log::info!("Sending command “{:?}” with remote “{remote_name}”.", somfy::Command::Prog);
+      remote.send_hold(&mut sender, &mut storage, somfy::Command::Prog, PROG_HOLD)?;
+
+      if confirm("Did the blind move up and down briefly?") {
+        println!("Paired remote “{remote_name}”.");
+      } else {
+        storage.remove_remote(remote_name)?;
+        eprintln!("Pairing failed, removed remote “{remote_name}”.");
+        exit(1);
+      }
+    },
+    Some("unpair") => {
+      let matches = matches.subcommand_matches("unpair").unwrap();
+      let remote_name: &String = matches.get_one("remote").unwrap();
+
+      let Some(remote) = storage.remote(remote_name) else {
+        eprintln!("No remote with name “{remote_name}” found.");
+        exit(1);
+      };
+      let mut remote = remote.clone();
+
+      prompt(
+        "Hold the Prog button of an existing remote of the blind until it moves up and down briefly, then press Enter.",
+      );
+
+      log::info!("Sending command “{:?}” with remote “{remote_name}”.", somfy::Command::Prog);
+      remote.send_hold(&mut sender, &mut storage, somfy::Command::Prog, PROG_HOLD)?;
+
+      if !confirm("Did the blind move up and down briefly?") {
+        eprintln!("Unpairing remote “{remote_name}” failed.");
+        exit(1);
+      }
+
+      println!("Unpaired remote “{remote_name}”.");
+
+      if matches.get_flag("remove") {
+        storage.remove_remote(remote_name)?;
+        state.remove(remote_name)?;
+        println!("Removed remote “{remote_name}”.");
+      }
+    },
+    Some("resync") => {
+      let matches = matches.subcommand_matches("resync").unwrap();
+
+      let remote_name: &String = matches.get_one("remote").unwrap();
+      let command: somfy::Command = matches.get_one("command").copied().unwrap();
+      let step: u16 = matches.get_one("step").copied().unwrap();
+      let max: u16 = matches.get_one("max").copied().unwrap();
+
+      let Some(remote) = storage.remote(remote_name) else {
+        eprintln!("No remote with name “{remote_name}” found.");
+        exit(1);
+      };
+
+      let mut remote = remote.clone();
+      let start = remote.rolling_code();
+
+      let advanced = remote.resync(&mut sender, &mut storage, command, step, max, |advanced| {
+        confirm(&format!(
+          "Sent “{command:?}” with rolling code {}. Did the motor react?",
+          start.wrapping_add(advanced - 1)
+        ))
+      })?;
+      remote.commit(&mut storage)?;
+
+      match advanced {
+        Some(advanced) => {
+          println!("Advanced rolling code of “{remote_name}” by {advanced} to {}.", remote.rolling_code())
+        },
+        None => {
+          eprintln!("Motor did not react after advancing rolling code of “{remote_name}” by up to {max}.");
+          exit(1);
+        },
+      }
+    },
     Some(subcommand_name) => {
       let matches = matches.subcommand_matches(subcommand_name).unwrap();
 
       let command = subcommand_name.parse::<somfy::Command>().unwrap();
       let remote_name: &String = matches.get_one("remote").unwrap();
-      let repetitions: usize = matches.get_one("repetitions").copied().unwrap();
+      let hold: Option<Duration> = matches.get_one("hold").copied();
+
+      if let Some(config) = storage.remote_config(remote_name) {
+        let config = config.clone();
+        let repetitions: usize = matches.get_one("repetitions").copied().or(config.repetitions).unwrap_or(0);
+        let mut remote = config.remote.clone();
 
-      if let Some(remote) = storage.remote(remote_name) {
         log::info!("Sending command “{command:?}” with remote “{remote_name}”.");
-        remote.clone().send_repeat(&mut sender, &mut storage, command, repetitions)?;
+        match hold {
+          Some(hold) => remote.send_hold(&mut sender, &mut storage, command, hold)?,
+          None => remote.send_repeat(&mut sender, &mut storage, command, repetitions)?,
+        }
+
+        state.command(remote_name, &config, command)?;
       } else {
         eprintln!("No remote with name “{remote_name}” found.");
         exit(1);
@@ -113,3 +565,128 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
   Ok(())
 }
+
+fn parse_address(s: &str) -> Result<u24, String> {
+  let address = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+    Some(hex) => u32::from_str_radix(hex, 16),
+    None => s.parse::<u32>(),
+  };
+
+  match address {
+    Ok(address) if address <= 0xFFFFFF => Ok(u24::new(address)),
+    Ok(_) => Err(String::from("address must fit into 24 bits")),
+    Err(err) => Err(err.to_string()),
+  }
+}
+
+fn manage_remotes(matches: &ArgMatches, storage: &mut Storage, state: &mut StateFile) -> Result<(), Box<dyn Error>> {
+  match matches.subcommand() {
+    Some(("add", matches)) => {
+      let remote_name: &String = matches.get_one("remote").unwrap();
+      let address = matches.get_one::<u24>("address").copied().unwrap_or_else(|| storage.unused_address());
+      let rolling_code: u16 = matches.get_one("rolling-code").copied().unwrap();
+
+      storage.add_remote(remote_name.clone(), Remote::new(address, rolling_code))?;
+      println!("Added remote “{remote_name}” with address {address}.");
+    },
+    Some(("rm", matches)) => {
+      let remote_name: &String = matches.get_one("remote").unwrap();
+
+      if storage.remove_remote(remote_name)?.is_none() {
+        eprintln!("No remote with name “{remote_name}” found.");
+        exit(1);
+      }
+      state.remove(remote_name)?;
+      println!("Removed remote “{remote_name}”.");
+    },
+    Some(("mv", matches)) => {
+      let remote_name: &String = matches.get_one("remote").unwrap();
+      let new_name: &String = matches.get_one("new_name").unwrap();
+
+      storage.rename_remote(remote_name, new_name.clone())?;
+      state.rename(remote_name, new_name)?;
+      println!("Renamed remote “{remote_name}” to “{new_name}”.");
+    },
+    Some(("set", matches)) => {
+      let remote_name: &String = matches.get_one("remote").unwrap();
+
+      if let Some(&address) = matches.get_one::<u24>("address") {
+        storage.set_address(remote_name, address)?;
+        println!("Changed address of remote “{remote_name}” to {address}.");
+      }
+
+      if let Some(&rolling_code) = matches.get_one::<u16>("rolling-code") {
+        storage.set_rolling_code(remote_name, rolling_code)?;
+        println!("Changed rolling code of remote “{remote_name}” to {rolling_code}.");
+      }
+    },
+    Some(("list", _)) => {
+      for (name, config) in storage.remotes() {
+        let remote = &config.remote;
+        println!(
+          "{name}: address {}, rolling code {}, key {:?}",
+          remote.address(),
+          remote.rolling_code(),
+          remote.key()
+        );
+      }
+    },
+    _ => unreachable!(),
+  }
+
+  Ok(())
+}
+
+fn prompt(message: &str) -> String {
+  print!("{message} ");
+  let _ = io::stdout().flush();
+
+  let mut answer = String::new();
+  let _ = io::stdin().read_line(&mut answer);
+  answer.trim().to_owned()
+}
+
+fn confirm(question: &str) -> bool {
+  matches!(prompt(&format!("{question} [y/N]")).as_str(), "y" | "Y" | "yes" | "Yes")
+}
+
+fn receive(gpio: &Gpio, receiver_pin: u8, storage: &Storage, timing: TimingProfile) -> Result<(), Box<dyn Error>> {
+  let mut pin = gpio.get(receiver_pin)?.into_input();
+  pin.set_interrupt(Trigger::Both)?;
+
+  // rppal does not expose the kernel's timestamps of the edges, so they are only timestamped once
+  // `poll_interrupt` returns, which adds the scheduling latency to every pulse. Therefore, use the widest
+  // symbol tolerance which still tells half and full symbols apart.
+  let tolerance = Tolerance { symbol: timing.symbol_width / 4, ..Tolerance::for_timing(&timing) };
+
+  let mut receiver = Receiver::with_demodulator(pin, Demodulator::with_timing(timing, tolerance));
+  let start = Instant::now();
+
+  log::info!("Receiving frames on pin {receiver_pin}.");
+
+  loop {
+    let level = receiver.pin_mut().poll_interrupt(false, Some(RECEIVER_POLL_TIMEOUT))?;
+    let timestamp = start.elapsed().as_micros() as u64;
+
+    let press = match level {
+      Some(level) => receiver.edge_with_state(PinState::from(level == Level::High), timestamp),
+      None => receiver.poll(timestamp),
+    };
+
+    match press {
+      Some(Ok(ButtonPress { frame, repetitions, .. })) => {
+        let address = frame.remote_address();
+        let remote_name = storage.remote_name(address).unwrap_or("unknown remote");
+
+        println!(
+          "{remote_name}: {:?} (address {address}, rolling code {}, key {:#04X}, {repetitions} repetitions)",
+          frame.command(),
+          frame.rolling_code(),
+          frame.key(),
+        );
+      },
+      Some(Err(err)) => log::warn!("Failed to decode frame: {err}"),
+      None => (),
+    }
+  }
+}
@@ -0,0 +1,249 @@
+use core::time::Duration;
+
+use crate::{Command, Direction};
+
+/// Fully open, in millionths of the travel range.
+const OPEN: u64 = 1_000_000;
+
+/// A blind which is currently moving.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Motion {
+  pub direction: Direction,
+  /// Timestamp at which the blind started moving.
+  pub since: Duration,
+}
+
+/// Estimates the position of a blind from the commands sent to it and its travel times.
+///
+/// Positions are given in percent, where 0 is fully closed and 100 is fully open. Timestamps are
+/// given as the time elapsed since an arbitrary, but fixed point in time, e.g. the start of the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PositionEstimator {
+  open_time: Duration,
+  close_time: Duration,
+  my_position: Option<u8>,
+  /// Position at the start of the current motion.
+  position: u64,
+  motion: Option<Motion>,
+  /// Position at which the current motion ends.
+  target: u64,
+}
+
+impl PositionEstimator {
+  /// Create a new `PositionEstimator` for a blind which needs `open_time` to open fully
+  /// and `close_time` to close fully, starting at the given `position` in percent.
+  pub fn new(open_time: Duration, close_time: Duration, position: u8) -> Self {
+    let position = from_percent(position);
+    Self { open_time, close_time, my_position: None, position, motion: None, target: position }
+  }
+
+  /// Set the favourite position in percent, which the blind moves to when My is sent while it is stopped.
+  pub fn with_my_position(mut self, my_position: Option<u8>) -> Self {
+    self.set_my_position(my_position);
+    self
+  }
+
+  pub fn my_position(&self) -> Option<u8> {
+    self.my_position
+  }
+
+  pub fn set_my_position(&mut self, my_position: Option<u8>) {
+    self.my_position = my_position.map(|my_position| my_position.min(100));
+  }
+
+  pub fn open_time(&self) -> Duration {
+    self.open_time
+  }
+
+  pub fn close_time(&self) -> Duration {
+    self.close_time
+  }
+
+  /// The current motion, if the blind was started and not stopped since.
+  ///
+  /// Note that this does not take end stops into account, use `is_moving` for that.
+  pub fn motion(&self) -> Option<Motion> {
+    self.motion
+  }
+
+  /// The estimated position in percent at the given timestamp.
+  pub fn position(&self, now: Duration) -> u8 {
+    to_percent(self.position_at(now))
+  }
+
+  /// Set the position in percent, e.g. after the blind was moved without sending a command.
+  pub fn set_position(&mut self, position: u8) {
+    self.position = from_percent(position);
+    self.motion = None;
+    self.target = self.position;
+  }
+
+  /// The position in percent at which the current motion ends by itself, if the blind is moving.
+  pub fn target(&self, now: Duration) -> Option<u8> {
+    self.is_moving(now).then(|| to_percent(self.target))
+  }
+
+  /// Whether the blind is estimated to still be moving at the given timestamp.
+  pub fn is_moving(&self, now: Duration) -> bool {
+    self.motion.is_some() && self.position_at(now) != self.target
+  }
+
+  /// Update the estimate after the blind was started in the given `direction`.
+  pub fn start(&mut self, direction: Direction, now: Duration) {
+    let target = match direction {
+      Direction::Up => 100,
+      Direction::Down => 0,
+    };
+
+    self.move_towards(target, now);
+  }
+
+  /// Update the estimate after the blind was started towards a `target` position in percent,
+  /// at which it stops by itself.
+  pub fn move_towards(&mut self, target: u8, now: Duration) {
+    self.position = self.position_at(now);
+    self.target = from_percent(target);
+
+    let direction = if self.target >= self.position { Direction::Up } else { Direction::Down };
+    self.motion = Some(Motion { direction, since: now });
+  }
+
+  /// Update the estimate after the blind was stopped.
+  pub fn stop(&mut self, now: Duration) {
+    self.position = self.position_at(now);
+    self.motion = None;
+    self.target = self.position;
+  }
+
+  /// Update the estimate after a `command` was sent.
+  ///
+  /// My stops a moving blind and moves a stopped blind to its favourite position, if known.
+  pub fn command(&mut self, command: Command, now: Duration) {
+    match command {
+      Command::Up => self.start(Direction::Up, now),
+      Command::Down => self.start(Direction::Down, now),
+      Command::My if self.is_moving(now) => self.stop(now),
+      Command::My => {
+        if let Some(my_position) = self.my_position {
+          self.move_towards(my_position, now)
+        }
+      },
+      _ => (),
+    }
+  }
+
+  /// The direction to move in and the time after which the blind has to be stopped to reach the `target`
+  /// position in percent, or `None` if the blind is already there.
+  ///
+  /// When moving to 0 or 100, the blind stops by itself, so it does not need to be stopped.
+  /// Since this also corrects any accumulated error, the blind is always moved in that case.
+  pub fn move_to(&self, target: u8, now: Duration) -> Option<(Direction, Duration)> {
+    let position = self.position_at(now);
+    let target = from_percent(target);
+
+    let (direction, distance, full_time) = if target == OPEN || target > position {
+      (Direction::Up, target - position, self.open_time)
+    } else if target == 0 || target < position {
+      (Direction::Down, position - target, self.close_time)
+    } else {
+      return None
+    };
+
+    let full_time = u64::try_from(full_time.as_micros()).unwrap_or(u64::MAX);
+    Some((direction, Duration::from_micros(distance * full_time / OPEN)))
+  }
+
+  fn position_at(&self, now: Duration) -> u64 {
+    let Some(motion) = self.motion else { return self.position };
+
+    let elapsed = u64::try_from(now.saturating_sub(motion.since).as_micros()).unwrap_or(u64::MAX);
+    let travelled = |full_time: Duration| {
+      let full_time = u64::try_from(full_time.as_micros()).unwrap_or(u64::MAX).max(1);
+      elapsed.saturating_mul(OPEN) / full_time
+    };
+
+    match motion.direction {
+      Direction::Up => self.position.saturating_add(travelled(self.open_time)).min(self.target),
+      Direction::Down => self.position.saturating_sub(travelled(self.close_time)).max(self.target),
+    }
+  }
+}
+
+fn from_percent(position: u8) -> u64 {
+  u64::from(position.min(100)) * OPEN / 100
+}
+
+fn to_percent(position: u64) -> u8 {
+  ((position * 100 + OPEN / 2) / OPEN) as u8
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const OPEN_TIME: Duration = Duration::from_secs(20);
+  const CLOSE_TIME: Duration = Duration::from_secs(16);
+
+  fn secs(secs: u64) -> Duration {
+    Duration::from_secs(secs)
+  }
+
+  #[test]
+  fn test_position() {
+    let mut estimator = PositionEstimator::new(OPEN_TIME, CLOSE_TIME, 0);
+
+    estimator.command(Command::Up, secs(10));
+    assert!(estimator.is_moving(secs(10)));
+    assert_eq!(estimator.position(secs(15)), 25);
+
+    estimator.command(Command::My, secs(20));
+    assert!(!estimator.is_moving(secs(20)));
+    assert_eq!(estimator.position(secs(100)), 50);
+
+    estimator.command(Command::Down, secs(100));
+    assert_eq!(estimator.position(secs(104)), 25);
+    assert_eq!(estimator.position(secs(200)), 0);
+    assert!(!estimator.is_moving(secs(200)));
+
+    estimator.command(Command::Up, secs(200));
+    assert_eq!(estimator.position(secs(300)), 100);
+  }
+
+  #[test]
+  fn test_my_position() {
+    let mut estimator = PositionEstimator::new(OPEN_TIME, CLOSE_TIME, 100).with_my_position(Some(25));
+
+    estimator.command(Command::My, secs(0));
+    assert_eq!(estimator.target(secs(0)), Some(25));
+    assert_eq!(estimator.position(secs(4)), 75);
+    assert_eq!(estimator.position(secs(100)), 25);
+    assert!(!estimator.is_moving(secs(100)));
+
+    estimator.command(Command::Up, secs(100));
+    estimator.command(Command::My, secs(104));
+    assert_eq!(estimator.position(secs(200)), 45);
+
+    // Without a favourite position, My only stops the blind.
+    let mut estimator = PositionEstimator::new(OPEN_TIME, CLOSE_TIME, 100);
+    estimator.command(Command::My, secs(0));
+    assert_eq!(estimator.motion(), None);
+  }
+
+  #[test]
+  fn test_move_to() {
+    let mut estimator = PositionEstimator::new(OPEN_TIME, CLOSE_TIME, 50);
+
+    assert_eq!(estimator.move_to(50, secs(0)), None);
+    assert_eq!(estimator.move_to(75, secs(0)), Some((Direction::Up, secs(5))));
+    assert_eq!(estimator.move_to(25, secs(0)), Some((Direction::Down, secs(4))));
+
+    let (direction, duration) = estimator.move_to(30, secs(0)).unwrap();
+    estimator.start(direction, secs(0));
+    estimator.stop(duration);
+    assert_eq!(estimator.position(duration), 30);
+
+    // Moving to an end position always moves, even if it is already estimated to be there.
+    estimator.set_position(100);
+    assert_eq!(estimator.move_to(100, secs(0)), Some((Direction::Up, secs(0))));
+  }
+}
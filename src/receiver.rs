@@ -0,0 +1,259 @@
+use embedded_hal::digital::{InputPin, PinState};
+
+use crate::{Demodulator, Frame, FrameError};
+
+/// Maximum time in µs between two frames belonging to the same button press.
+const RELEASE_TIMEOUT: u64 = 250_000;
+
+/// A button press, i.e. a `Frame` together with the number of times it was repeated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ButtonPress {
+  pub frame: Frame,
+  pub repetitions: usize,
+  /// Timestamp in µs at which the first frame was received.
+  pub start: u64,
+  /// Timestamp in µs at which the last frame was received.
+  pub end: u64,
+}
+
+/// Receives frames from an `InputPin` connected to a 433.42 MHz receiver.
+///
+/// Instead of polling the pin, the `Receiver` expects to be notified about every
+/// edge on the pin together with a timestamp, e.g. from an interrupt handler.
+#[derive(Debug)]
+pub struct Receiver<P> {
+  pin: P,
+  demodulator: Demodulator,
+  release_timeout: u64,
+  last_edge: Option<(PinState, u64)>,
+  flushed: bool,
+  press: Option<ButtonPress>,
+}
+
+impl<P> Receiver<P> {
+  pub fn new(pin: P) -> Self {
+    Self::with_demodulator(pin, Demodulator::default())
+  }
+
+  pub fn with_demodulator(pin: P, demodulator: Demodulator) -> Self {
+    Self { pin, demodulator, release_timeout: RELEASE_TIMEOUT, last_edge: None, flushed: false, press: None }
+  }
+
+  /// Set the maximum time in µs between two frames belonging to the same button press.
+  pub fn release_timeout(&mut self, release_timeout: u64) -> &mut Self {
+    self.release_timeout = release_timeout;
+    self
+  }
+
+  pub fn pin_mut(&mut self) -> &mut P {
+    &mut self.pin
+  }
+
+  pub fn into_inner(self) -> P {
+    self.pin
+  }
+
+  /// Notify the receiver about an edge with the new pin `state` at the given `timestamp` in µs.
+  ///
+  /// Use this if the new pin state is reported together with the edge, otherwise use `Receiver::edge`.
+  ///
+  /// Returns a `ButtonPress` once all frames belonging to it have been received.
+  pub fn edge_with_state(&mut self, state: PinState, timestamp: u64) -> Option<Result<ButtonPress, FrameError>> {
+    let decoded = match self.last_edge.replace((state, timestamp)) {
+      Some((_, _)) if self.flushed => None,
+      Some((last_state, last_timestamp)) => {
+        let duration = timestamp.saturating_sub(last_timestamp).try_into().unwrap_or(u32::MAX);
+        self.demodulator.feed(last_state, duration)
+      },
+      None => None,
+    };
+    self.flushed = false;
+
+    match decoded {
+      Some(Ok(frame)) => {
+        let released = self.release(timestamp);
+        self.receive(frame, timestamp).or(released).map(Ok)
+      },
+      // The current press is released by the next call, so the error is not lost.
+      Some(Err(err)) => Some(Err(err)),
+      None => self.release(timestamp).map(Ok),
+    }
+  }
+
+  /// Check whether the current button press has been released at the given `timestamp` in µs.
+  ///
+  /// This should be called periodically when no edges are received, since the last frame
+  /// of a button press is only complete once the following inter-frame gap has been seen.
+  pub fn poll(&mut self, timestamp: u64) -> Option<Result<ButtonPress, FrameError>> {
+    if let Some((PinState::Low, last_timestamp)) = self.last_edge {
+      let duration = timestamp.saturating_sub(last_timestamp);
+
+      let max_symbol_width = self.demodulator.timing().symbol_width + self.demodulator.tolerance().symbol;
+      if !self.flushed && duration > u64::from(max_symbol_width) {
+        self.flushed = true;
+
+        match self.demodulator.feed(PinState::Low, duration.try_into().unwrap_or(u32::MAX)) {
+          Some(Ok(frame)) => {
+            if let Some(press) = self.receive(frame, last_timestamp) {
+              return Some(Ok(press))
+            }
+          },
+          Some(Err(err)) => return Some(Err(err)),
+          None => (),
+        }
+      }
+    }
+
+    self.release(timestamp).map(Ok)
+  }
+
+  fn receive(&mut self, frame: Frame, timestamp: u64) -> Option<ButtonPress> {
+    match self.press {
+      Some(ref mut press) if press.frame == frame => {
+        press.repetitions += 1;
+        press.end = timestamp;
+        None
+      },
+      _ => self.press.replace(ButtonPress { frame, repetitions: 0, start: timestamp, end: timestamp }),
+    }
+  }
+
+  fn release(&mut self, timestamp: u64) -> Option<ButtonPress> {
+    match self.press {
+      Some(press) if timestamp.saturating_sub(press.end) > self.release_timeout => self.press.take(),
+      _ => None,
+    }
+  }
+}
+
+impl<P, E> Receiver<P>
+where
+  P: InputPin<Error = E>,
+{
+  /// Notify the receiver about an edge at the given `timestamp` in µs.
+  ///
+  /// The new state is read from the pin, so this should be called as soon as possible after the edge.
+  ///
+  /// Returns a `ButtonPress` once all frames belonging to it have been received.
+  pub fn edge(&mut self, timestamp: u64) -> Result<Option<Result<ButtonPress, FrameError>>, E> {
+    let state = PinState::from(self.pin.is_high()?);
+    Ok(self.edge_with_state(state, timestamp))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use core::convert::Infallible;
+
+  use embedded_hal::digital::ErrorType;
+  use ux::u24;
+
+  use super::*;
+  use crate::{Command, Waveform};
+
+  struct NoPin;
+
+  impl ErrorType for NoPin {
+    type Error = Infallible;
+  }
+
+  fn frame(rolling_code: u16) -> Frame {
+    Frame::builder()
+      .key(0xA7)
+      .command(Command::Up)
+      .rolling_code(rolling_code)
+      .remote_address(u24::new(0xFFAA11))
+      .build()
+      .expect("Failed to build frame")
+  }
+
+  fn receive(
+    receiver: &mut Receiver<NoPin>,
+    frame: &Frame,
+    repetitions: usize,
+    timestamp: &mut u64,
+  ) -> Vec<ButtonPress> {
+    let mut presses = Vec::new();
+
+    for pulse in Waveform::for_frame(frame, repetitions).into_pulses() {
+      presses.extend(receiver.edge_with_state(pulse.state, *timestamp).map(Result::unwrap));
+      *timestamp += u64::from(pulse.duration);
+    }
+    presses.extend(receiver.edge_with_state(PinState::High, *timestamp).map(Result::unwrap));
+
+    presses
+  }
+
+  #[test]
+  fn test_receive() {
+    let mut receiver = Receiver::new(NoPin);
+    let mut timestamp = 0;
+
+    let first = frame(42);
+    let second = frame(43);
+
+    assert_eq!(receive(&mut receiver, &first, 2, &mut timestamp), vec![]);
+
+    let presses = receive(&mut receiver, &second, 0, &mut timestamp);
+    assert_eq!(presses.len(), 1);
+    assert_eq!(presses[0].frame, first);
+    assert_eq!(presses[0].repetitions, 2);
+
+    assert_eq!(receiver.poll(timestamp), None);
+
+    let press = receiver.poll(timestamp + RELEASE_TIMEOUT + 1).unwrap().unwrap();
+    assert_eq!(press.frame, second);
+    assert_eq!(press.repetitions, 0);
+  }
+
+  #[test]
+  fn test_poll_flushes_last_frame() {
+    let mut receiver = Receiver::new(NoPin);
+    let mut timestamp = 0;
+
+    let frame = frame(42);
+
+    for pulse in Waveform::for_frame(&frame, 0).into_pulses() {
+      assert_eq!(receiver.edge_with_state(pulse.state, timestamp), None);
+      timestamp += u64::from(pulse.duration);
+    }
+
+    assert_eq!(receiver.poll(timestamp), None);
+
+    let press = receiver.poll(timestamp + RELEASE_TIMEOUT).unwrap().unwrap();
+    assert_eq!(press.frame, frame);
+  }
+
+  #[test]
+  fn test_invalid_frame_on_release() {
+    let mut receiver = Receiver::new(NoPin);
+    let mut timestamp = 0;
+
+    let valid = frame(42);
+    assert_eq!(receive(&mut receiver, &valid, 0, &mut timestamp), vec![]);
+    let press_end = receiver.press.unwrap().end;
+
+    let mut bytes = *frame(43).as_bytes();
+    bytes[6] ^= 0b0001;
+    let invalid = Frame::from_bytes_unchecked(bytes);
+
+    // The press is released exactly on the edge completing the invalid frame, i.e. the start of the inter-frame gap.
+    let pulses = Waveform::for_frame(&invalid, 0).into_pulses();
+    let (gap, data) = pulses.split_last().unwrap();
+    let completed = timestamp + data.iter().map(|pulse| u64::from(pulse.duration)).sum::<u64>();
+    receiver.release_timeout(completed - press_end - 1);
+
+    let mut results = Vec::new();
+    for pulse in data {
+      assert_eq!(receiver.edge_with_state(pulse.state, timestamp), None);
+      timestamp += u64::from(pulse.duration);
+    }
+
+    results.extend(receiver.edge_with_state(gap.state, timestamp));
+    results.extend(receiver.edge_with_state(PinState::High, timestamp + u64::from(gap.duration)));
+
+    assert!(
+      matches!(results[..], [Err(FrameError::InvalidChecksum { .. }), Ok(ButtonPress { frame, .. })] if frame == valid)
+    );
+  }
+}
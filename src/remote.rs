@@ -1,3 +1,5 @@
+use core::time::Duration;
+
 use embedded_hal::{delay::DelayNs, digital::OutputPin};
 use ux::u24;
 
@@ -8,21 +10,48 @@ use super::*;
 pub struct Remote {
   address: u24,
   rolling_code: u16,
+  #[cfg_attr(feature = "serde", serde(default))]
+  key: KeyStrategy,
+  /// Number of rolling codes, starting with the current one, which have already been persisted as used.
+  #[cfg_attr(feature = "serde", serde(skip))]
+  reserved: u16,
 }
 
 impl Remote {
+  /// Duration of a My press which stores the current position as the favourite position.
+  pub const MY_POSITION_HOLD: Duration = Duration::from_secs(5);
+
   pub fn new(address: u24, rolling_code: u16) -> Self {
-    Self { address, rolling_code }
+    Self { address, rolling_code, key: KeyStrategy::default(), reserved: 0 }
+  }
+
+  pub fn with_key(mut self, key: KeyStrategy) -> Self {
+    self.key = key;
+    self
   }
 
   pub fn address(&self) -> u24 {
     self.address
   }
 
+  /// The next unused rolling code.
+  ///
+  /// Rolling codes wrap around from 65535 to 0, just like they do on the motor.
   pub fn rolling_code(&self) -> u16 {
     self.rolling_code
   }
 
+  /// Skip the given number of rolling codes without sending them, wrapping around if necessary.
+  pub fn advance_rolling_code(&mut self, count: u16) {
+    self.rolling_code = self.rolling_code.wrapping_add(count);
+    // Reserved codes which were skipped are no longer available.
+    self.reserved = self.reserved.saturating_sub(count);
+  }
+
+  pub fn key(&self) -> KeyStrategy {
+    self.key
+  }
+
   pub fn send<T, D, E, S, SE>(
     &mut self,
     sender: &mut Sender<T, D>,
@@ -48,23 +77,428 @@ impl Remote {
     S: SendFrame<Error = TE>,
     CS: RollingCodeStorage<Error = SE>,
   {
-    let frame = Frame::builder()
-      .key(0xA7)
-      .command(command)
-      .remote_address(self.address)
-      .rolling_code(self.rolling_code)
-      .build()
-      .unwrap();
-
-    if let Err(err) = sender.send_frame_repeat(&frame, repetitions) {
-      return Err(Error::TransmitError(err))
+    let frame = self.reserve_frame(storage, command)?;
+
+    let result = sender.send_frame_repeat(&frame, repetitions);
+    Self::record_transmission(storage, &frame, repetitions, result)
+  }
+
+  /// Send a `command` as if the button on a remote was held down for the given `hold` duration.
+  ///
+  /// The number of repetitions is calculated from the `TimingProfile` of the `sender`.
+  pub fn send_hold<S, TE, CS, SE>(
+    &mut self,
+    sender: &mut S,
+    storage: &mut CS,
+    command: Command,
+    hold: Duration,
+  ) -> Result<(), Error<TE, SE>>
+  where
+    S: SendFrame<Error = TE>,
+    CS: RollingCodeStorage<Error = SE>,
+  {
+    let repetitions = sender.timing().repetitions_for(hold);
+    self.send_repeat(sender, storage, command, repetitions)
+  }
+
+  /// Store the current position of the blind as its favourite position using a long My press.
+  pub fn store_my_position<S, TE, CS, SE>(&mut self, sender: &mut S, storage: &mut CS) -> Result<(), Error<TE, SE>>
+  where
+    S: SendFrame<Error = TE>,
+    CS: RollingCodeStorage<Error = SE>,
+  {
+    self.send_hold(sender, storage, Command::My, Self::MY_POSITION_HOLD)
+  }
+
+  /// Tilt the slats of a venetian blind by the given number of `steps`.
+  ///
+  /// Each step is sent as a separate short press, which venetian motors interpret as a tilt step.
+  pub fn tilt_step<S, TE, CS, SE>(
+    &mut self,
+    sender: &mut S,
+    storage: &mut CS,
+    direction: Direction,
+    steps: usize,
+  ) -> Result<(), Error<TE, SE>>
+  where
+    S: SendFrame<Error = TE>,
+    CS: RollingCodeStorage<Error = SE>,
+  {
+    for _ in 0..steps {
+      self.send_repeat(sender, storage, direction.command(), 0)?;
     }
 
-    self.rolling_code += 1;
-    if let Err(err) = storage.persist(&*self) {
-      return Err(Error::StorageError(err))
+    Ok(())
+  }
+
+  /// Move a venetian blind all the way in the given `direction` using a long press.
+  pub fn travel<S, TE, CS, SE>(
+    &mut self,
+    sender: &mut S,
+    storage: &mut CS,
+    direction: Direction,
+  ) -> Result<(), Error<TE, SE>>
+  where
+    S: SendFrame<Error = TE>,
+    CS: RollingCodeStorage<Error = SE>,
+  {
+    self.send_hold(sender, storage, direction.command(), Direction::TRAVEL_HOLD)
+  }
+
+  #[cfg(feature = "async")]
+  pub async fn send_async<S, TE, CS, SE>(
+    &mut self,
+    sender: &mut S,
+    storage: &mut CS,
+    command: Command,
+  ) -> Result<(), Error<TE, SE>>
+  where
+    S: AsyncSendFrame<Error = TE>,
+    CS: RollingCodeStorage<Error = SE>,
+  {
+    self.send_repeat_async(sender, storage, command, 0).await
+  }
+
+  #[cfg(feature = "async")]
+  pub async fn send_repeat_async<S, TE, CS, SE>(
+    &mut self,
+    sender: &mut S,
+    storage: &mut CS,
+    command: Command,
+    repetitions: usize,
+  ) -> Result<(), Error<TE, SE>>
+  where
+    S: AsyncSendFrame<Error = TE>,
+    CS: RollingCodeStorage<Error = SE>,
+  {
+    let frame = self.reserve_frame(storage, command)?;
+
+    let result = sender.send_frame_repeat(&frame, repetitions).await;
+    Self::record_transmission(storage, &frame, repetitions, result)
+  }
+
+  /// Send a `command` as if the button on a remote was held down for the given `hold` duration.
+  ///
+  /// The number of repetitions is calculated from the `TimingProfile` of the `sender`.
+  #[cfg(feature = "async")]
+  pub async fn send_hold_async<S, TE, CS, SE>(
+    &mut self,
+    sender: &mut S,
+    storage: &mut CS,
+    command: Command,
+    hold: Duration,
+  ) -> Result<(), Error<TE, SE>>
+  where
+    S: AsyncSendFrame<Error = TE>,
+    CS: RollingCodeStorage<Error = SE>,
+  {
+    let repetitions = sender.timing().repetitions_for(hold);
+    self.send_repeat_async(sender, storage, command, repetitions).await
+  }
+
+  /// Resynchronise a motor which no longer accepts commands because its last seen rolling code is ahead.
+  ///
+  /// The `command` is sent, and `confirm` is called with the number of rolling codes advanced so far
+  /// to ask whether the motor reacted. If not, `step - 1` rolling codes are skipped before trying again,
+  /// as long as this does not advance the rolling code by more than `max` in total.
+  ///
+  /// Since a motor only accepts a limited window of rolling codes ahead of the last one it has seen,
+  /// `step` should be smaller than that window.
+  ///
+  /// Returns the number of rolling codes advanced once confirmed, or `None` if the motor never reacted.
+  pub fn resync<S, TE, CS, SE, F>(
+    &mut self,
+    sender: &mut S,
+    storage: &mut CS,
+    command: Command,
+    step: u16,
+    max: u16,
+    mut confirm: F,
+  ) -> Result<Option<u16>, Error<TE, SE>>
+  where
+    S: SendFrame<Error = TE>,
+    CS: RollingCodeStorage<Error = SE>,
+    F: FnMut(u16) -> bool,
+  {
+    let start = self.rolling_code;
+
+    loop {
+      self.send_repeat(sender, storage, command, 0)?;
+
+      let advanced = self.rolling_code.wrapping_sub(start);
+      if confirm(advanced) {
+        return Ok(Some(advanced))
+      }
+
+      if advanced.saturating_add(step.max(1)) > max {
+        return Ok(None)
+      }
+
+      self.advance_rolling_code(step.saturating_sub(1));
     }
+  }
+
+  /// Reserve the current rolling code for sending a frame.
+  ///
+  /// Before the rolling code is used, the next one is persisted, so that a rolling code is never
+  /// reused, even if the process dies before or during transmission. Depending on
+  /// `RollingCodeStorage::reservation_size`, a whole block of rolling codes is persisted at once.
+  pub fn reserve_rolling_code<CS, SE>(&mut self, storage: &mut CS) -> Result<u16, SE>
+  where
+    CS: RollingCodeStorage<Error = SE>,
+  {
+    if self.reserved == 0 {
+      let count = storage.reservation_size().max(1);
+
+      let reserved = Self { rolling_code: self.rolling_code.wrapping_add(count), reserved: 0, ..self.clone() };
+      storage.persist(&reserved)?;
+
+      self.reserved = count;
+    }
+
+    let rolling_code = self.rolling_code;
+    self.rolling_code = self.rolling_code.wrapping_add(1);
+    self.reserved -= 1;
+
+    Ok(rolling_code)
+  }
+
+  /// Persist the next unused rolling code, releasing any reserved but unused rolling codes.
+  pub fn commit<CS, SE>(&mut self, storage: &mut CS) -> Result<(), SE>
+  where
+    CS: RollingCodeStorage<Error = SE>,
+  {
+    storage.persist(self)?;
+    self.reserved = 0;
 
     Ok(())
   }
+
+  // Recording is best-effort, since the frame was already sent and its rolling code is persisted.
+  fn record_transmission<TE, CS, SE>(
+    storage: &mut CS,
+    frame: &Frame,
+    repetitions: usize,
+    result: Result<(), TE>,
+  ) -> Result<(), Error<TE, SE>>
+  where
+    CS: RollingCodeStorage<Error = SE>,
+  {
+    if storage.record_transmission(frame, repetitions, result.is_ok()).is_err() {
+      log::warn!("Failed to record transmission with rolling code {}.", frame.rolling_code());
+    }
+
+    result.map_err(Error::TransmitError)
+  }
+
+  fn reserve_frame<TE, CS, SE>(&mut self, storage: &mut CS, command: Command) -> Result<Frame, Error<TE, SE>>
+  where
+    CS: RollingCodeStorage<Error = SE>,
+  {
+    let rolling_code = match self.reserve_rolling_code(storage) {
+      Ok(rolling_code) => rolling_code,
+      Err(err) => return Err(Error::StorageError(err)),
+    };
+
+    Ok(
+      Frame::builder()
+        .key(self.key.key(rolling_code))
+        .command(command)
+        .remote_address(self.address)
+        .rolling_code(rolling_code)
+        .build()
+        .unwrap(),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use alloc::{rc::Rc, vec::Vec};
+  use core::cell::RefCell;
+
+  use super::*;
+
+  #[derive(Debug, Clone, PartialEq, Eq)]
+  enum Event {
+    Persist(u16),
+    Send(u16),
+  }
+
+  #[derive(Debug, Default)]
+  struct MockStorage {
+    events: Rc<RefCell<Vec<Event>>>,
+    reservation_size: u16,
+    fail: bool,
+    transmissions: Vec<(u16, usize, bool)>,
+  }
+
+  impl RollingCodeStorage for MockStorage {
+    type Error = ();
+
+    fn persist(&mut self, remote: &Remote) -> Result<(), Self::Error> {
+      if self.fail {
+        return Err(())
+      }
+
+      self.events.borrow_mut().push(Event::Persist(remote.rolling_code()));
+      Ok(())
+    }
+
+    fn reservation_size(&self) -> u16 {
+      self.reservation_size
+    }
+
+    fn record_transmission(&mut self, frame: &Frame, repetitions: usize, sent: bool) -> Result<(), Self::Error> {
+      self.transmissions.push((frame.rolling_code(), repetitions, sent));
+      Ok(())
+    }
+  }
+
+  struct MockSender {
+    events: Rc<RefCell<Vec<Event>>>,
+    repetitions: usize,
+  }
+
+  impl SendFrame for MockSender {
+    type Error = ();
+
+    fn send_frame(&mut self, frame: &Frame) -> Result<(), Self::Error> {
+      self.send_frame_repeat(frame, 0)
+    }
+
+    fn send_frame_repeat(&mut self, frame: &Frame, repetitions: usize) -> Result<(), Self::Error> {
+      self.repetitions = repetitions;
+      self.events.borrow_mut().push(Event::Send(frame.rolling_code()));
+      Ok(())
+    }
+  }
+
+  fn mocks(reservation_size: u16) -> (MockSender, MockStorage, Rc<RefCell<Vec<Event>>>) {
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let sender = MockSender { events: events.clone(), repetitions: 0 };
+    let storage = MockStorage { events: events.clone(), reservation_size, fail: false, transmissions: Vec::new() };
+    (sender, storage, events)
+  }
+
+  #[test]
+  fn test_persist_before_send() {
+    let (mut sender, mut storage, events) = mocks(1);
+
+    let mut remote = Remote::new(u24::new(0xFFAA11), 42);
+    remote.send_repeat(&mut sender, &mut storage, Command::Up, 0).unwrap();
+    remote.send_repeat(&mut sender, &mut storage, Command::Down, 0).unwrap();
+
+    assert_eq!(*events.borrow(), [Event::Persist(43), Event::Send(42), Event::Persist(44), Event::Send(43)]);
+    assert_eq!(remote.rolling_code(), 44);
+  }
+
+  #[test]
+  fn test_reserve_block() {
+    let (mut sender, mut storage, events) = mocks(10);
+
+    let mut remote = Remote::new(u24::new(0xFFAA11), 42);
+    for _ in 0..11 {
+      remote.send_repeat(&mut sender, &mut storage, Command::Up, 0).unwrap();
+    }
+
+    let persisted =
+      events.borrow().iter().filter(|event| matches!(event, Event::Persist(_))).cloned().collect::<Vec<_>>();
+    assert_eq!(persisted, [Event::Persist(52), Event::Persist(62)]);
+
+    remote.commit(&mut storage).unwrap();
+    assert_eq!(events.borrow().last(), Some(&Event::Persist(53)));
+
+    remote.send_repeat(&mut sender, &mut storage, Command::Up, 0).unwrap();
+    assert_eq!(events.borrow()[events.borrow().len() - 2..], [Event::Persist(63), Event::Send(53)]);
+  }
+
+  #[test]
+  fn test_send_hold() {
+    let (mut sender, mut storage, events) = mocks(1);
+
+    let mut remote = Remote::new(u24::new(0xFFAA11), 42);
+    remote.send_hold(&mut sender, &mut storage, Command::My, Duration::from_secs(5)).unwrap();
+
+    assert_eq!(sender.repetitions, TimingProfile::SOMFY.repetitions_for(Duration::from_secs(5)));
+    assert_eq!(*events.borrow(), [Event::Persist(43), Event::Send(42)]);
+  }
+
+  #[test]
+  fn test_tilt_step() {
+    let (mut sender, mut storage, events) = mocks(1);
+
+    let mut remote = Remote::new(u24::new(0xFFAA11), 42);
+    remote.tilt_step(&mut sender, &mut storage, Direction::Down, 3).unwrap();
+
+    let sent = events.borrow().iter().filter(|event| matches!(event, Event::Send(_))).count();
+    assert_eq!(sent, 3);
+    assert_eq!(sender.repetitions, 0);
+
+    remote.travel(&mut sender, &mut storage, Direction::Up).unwrap();
+    assert!(sender.repetitions > 0);
+    assert_eq!(sender.repetitions, TimingProfile::SOMFY.repetitions_for(Direction::TRAVEL_HOLD));
+  }
+
+  #[test]
+  fn test_rolling_code_wraps_around() {
+    let (mut sender, mut storage, events) = mocks(1);
+
+    let mut remote = Remote::new(u24::new(0xFFAA11), u16::MAX);
+    remote.send_repeat(&mut sender, &mut storage, Command::Up, 0).unwrap();
+
+    assert_eq!(*events.borrow(), [Event::Persist(0), Event::Send(u16::MAX)]);
+    assert_eq!(remote.rolling_code(), 0);
+  }
+
+  #[test]
+  fn test_resync() {
+    let (mut sender, mut storage, events) = mocks(4);
+
+    let mut remote = Remote::new(u24::new(0xFFAA11), 42);
+    let advanced = remote.resync(&mut sender, &mut storage, Command::My, 10, 100, |advanced| advanced > 25).unwrap();
+
+    assert_eq!(advanced, Some(31));
+    assert_eq!(remote.rolling_code(), 73);
+
+    let sent = events.borrow().iter().filter(|event| matches!(event, Event::Send(_))).cloned().collect::<Vec<_>>();
+    assert_eq!(sent, [Event::Send(42), Event::Send(52), Event::Send(62), Event::Send(72)]);
+
+    // Every sent rolling code was persisted as used before sending it.
+    let mut persisted = 0;
+    for event in events.borrow().iter() {
+      match *event {
+        Event::Persist(rolling_code) => persisted = rolling_code,
+        Event::Send(rolling_code) => assert!(rolling_code < persisted),
+      }
+    }
+
+    let mut remote = Remote::new(u24::new(0xFFAA11), 42);
+    let advanced = remote.resync(&mut sender, &mut storage, Command::My, 10, 100, |_| false).unwrap();
+    assert_eq!(advanced, None);
+  }
+
+  #[test]
+  fn test_record_transmission() {
+    let (mut sender, mut storage, _) = mocks(1);
+
+    let mut remote = Remote::new(u24::new(0xFFAA11), 42);
+    remote.send_repeat(&mut sender, &mut storage, Command::Up, 3).unwrap();
+    remote.tilt_step(&mut sender, &mut storage, Direction::Down, 2).unwrap();
+
+    assert_eq!(storage.transmissions, [(42, 3, true), (43, 0, true), (44, 0, true)]);
+  }
+
+  #[test]
+  fn test_storage_error_prevents_send() {
+    let (mut sender, mut storage, events) = mocks(1);
+    storage.fail = true;
+
+    let mut remote = Remote::new(u24::new(0xFFAA11), 42);
+    let result = remote.send_repeat(&mut sender, &mut storage, Command::Up, 0);
+
+    assert!(matches!(result, Err(Error::StorageError(()))));
+    assert!(events.borrow().is_empty());
+    assert_eq!(remote.rolling_code(), 42);
+  }
 }
@@ -2,25 +2,24 @@ use core::fmt;
 
 use embedded_hal::{
   delay::DelayNs,
-  digital::{
-    OutputPin,
-    PinState::{self, *},
-  },
+  digital::{OutputPin, PinState},
 };
 
-use crate::{Frame, SendFrame};
-
-const SYMBOL_WIDTH: u32 = 1280;
-
-#[derive(Debug, Clone, Copy)]
-enum SyncType {
-  Once,
-  Repeat,
-}
+#[cfg(feature = "async")]
+use crate::AsyncSendFrame;
+use crate::{waveform::pulses, Frame, SendFrame, TimingProfile};
 
 pub struct Sender<T, D> {
   pub transmitter: T,
   pub delay: D,
+  pub timing: TimingProfile,
+}
+
+impl<T, D> Sender<T, D> {
+  /// Create a new `Sender` using the default `TimingProfile`.
+  pub const fn new(transmitter: T, delay: D) -> Self {
+    Self { transmitter, delay, timing: TimingProfile::SOMFY }
+  }
 }
 
 impl<T, D> fmt::Debug for Sender<T, D>
@@ -28,7 +27,7 @@ where
   T: fmt::Debug,
 {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    f.debug_struct("Sender").field("transmitter", &self.transmitter).finish()
+    f.debug_struct("Sender").field("transmitter", &self.transmitter).field("timing", &self.timing).finish()
   }
 }
 
@@ -39,6 +38,10 @@ where
 {
   type Error = E;
 
+  fn timing(&self) -> TimingProfile {
+    self.timing
+  }
+
   /// Send a `Frame` once.
   fn send_frame(&mut self, frame: &Frame) -> Result<(), Self::Error> {
     self.send_frame_repeat(frame, 0)
@@ -47,82 +50,93 @@ where
   /// Send a `Frame` with a given number of `repetitions`. The total number sent is
   /// `1 + repetitions`, i.e. `send_frame(…)` is the same as `send_frame_repeat(…, 0)`.
   fn send_frame_repeat(&mut self, frame: &Frame, repetitions: usize) -> Result<(), Self::Error> {
-    self.wake_up()?;
-
-    self.send_frame_with_type(frame, SyncType::Once)?;
-
-    for _ in 0..repetitions {
-      self.send_frame_with_type(frame, SyncType::Repeat)?;
+    for pulse in pulses(frame, repetitions, &self.timing) {
+      self.send_state(pulse.state, pulse.duration)?;
     }
 
     Ok(())
   }
 }
 
-impl<T, D, E> Sender<T, D>
+#[cfg(feature = "async")]
+impl<T, D, E> AsyncSendFrame for Sender<T, D>
 where
   T: OutputPin<Error = E>,
-  D: DelayNs,
+  D: embedded_hal_async::delay::DelayNs,
 {
-  fn send_frame_with_type(&mut self, frame: &Frame, sync_type: SyncType) -> Result<(), E> {
-    self.hardware_sync(sync_type)?;
-    self.software_sync()?;
-
-    for &byte in frame.as_bytes() {
-      self.send_byte(byte)?;
-    }
+  type Error = E;
 
-    self.inter_frame_gap()
+  fn timing(&self) -> TimingProfile {
+    self.timing
   }
 
-  fn wake_up(&mut self) -> Result<(), E> {
-    self.send_state(High, 9_415)?;
-    self.send_state(Low, 89_565)
+  /// Send a `Frame` once.
+  async fn send_frame(&mut self, frame: &Frame) -> Result<(), Self::Error> {
+    self.send_frame_repeat(frame, 0).await
   }
 
-  fn hardware_sync(&mut self, sync_type: SyncType) -> Result<(), E> {
-    let sync_count = match sync_type {
-      SyncType::Once => 2,
-      SyncType::Repeat => 7,
-    };
-
-    for _ in 0..sync_count {
-      self.send_state(High, 2 * SYMBOL_WIDTH)?;
-      self.send_state(Low, 2 * SYMBOL_WIDTH)?;
+  /// Send a `Frame` with a given number of `repetitions`. The total number sent is
+  /// `1 + repetitions`, i.e. `send_frame(…)` is the same as `send_frame_repeat(…, 0)`.
+  async fn send_frame_repeat(&mut self, frame: &Frame, repetitions: usize) -> Result<(), Self::Error> {
+    for pulse in pulses(frame, repetitions, &self.timing) {
+      self.transmitter.set_state(pulse.state)?;
+      self.delay.delay_us(pulse.duration).await;
     }
 
     Ok(())
   }
+}
 
-  fn software_sync(&mut self) -> Result<(), E> {
-    self.send_state(High, 4_550)?;
-    self.send_state(Low, SYMBOL_WIDTH / 2)
-  }
-
-  fn inter_frame_gap(&mut self) -> Result<(), E> {
-    self.send_state(Low, 30_415)
-  }
-
+impl<T, D, E> Sender<T, D>
+where
+  T: OutputPin<Error = E>,
+  D: DelayNs,
+{
   fn send_state(&mut self, state: PinState, time: u32) -> Result<(), E> {
     self.transmitter.set_state(state)?;
     self.delay.delay_us(time);
     Ok(())
   }
+}
 
-  // Send a byte, starting with the most significant bit.
-  fn send_byte(&mut self, byte: u8) -> Result<(), E> {
-    for bit in 0..=7 {
-      self.send_bit((byte & (1 << (7 - bit))) != 0)?;
-    }
+#[cfg(all(test, feature = "async"))]
+mod tests {
+  use core::{
+    future::Future,
+    pin::pin,
+    task::{Context, Poll, Waker},
+  };
 
-    Ok(())
-  }
+  use ux::u24;
 
-  // Send a single bit, using Manchester encoding.
-  fn send_bit(&mut self, bit: bool) -> Result<(), E> {
-    let (from, to) = if bit { (Low, High) } else { (High, Low) };
+  use super::*;
+  use crate::{testing::VirtualClock, Command, Waveform};
+
+  fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut context = Context::from_waker(Waker::noop());
+
+    loop {
+      if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+        return output
+      }
+    }
+  }
 
-    self.send_state(from, SYMBOL_WIDTH / 2)?;
-    self.send_state(to, SYMBOL_WIDTH / 2)
+  #[test]
+  fn test_send_frame_async() {
+    let frame = Frame::builder()
+      .key(0xA7)
+      .command(Command::Up)
+      .rolling_code(42)
+      .remote_address(u24::new(0xFFAA11))
+      .build()
+      .expect("Failed to build frame");
+
+    let clock = VirtualClock::new();
+    let mut sender = Sender::new(clock.pin(), clock.delay());
+    block_on(AsyncSendFrame::send_frame_repeat(&mut sender, &frame, 1)).unwrap();
+
+    assert_eq!(clock.waveform(), Waveform::for_frame(&frame, 1));
   }
 }
@@ -1,142 +1,303 @@
-use core::cell::RefCell;
+use alloc::vec::Vec;
+use core::fmt;
 
-use embedded_hal::{
-  delay::DelayNs,
-  digital::{ErrorType, OutputPin, PinState},
-};
+use embedded_hal::{digital::PinState, spi::SpiBus};
 
-use crate::{Frame, SendFrame, Sender};
+use crate::{Frame, SendFrame, TimingProfile, Waveform};
 
-#[derive(Debug, Clone)]
-struct Pulse {
-  state: PinState,
-  duration: u32,
+/// Sends frames by clocking a bitstream out of the MOSI pin of an SPI bus.
+///
+/// Every bit on the bus represents the pin state for one clock period, so the timing only
+/// depends on the SPI clock instead of the scheduler. Since the whole bitstream is sent
+/// in as few transfers as possible, it is well-suited for DMA.
+pub struct SpiSender<S> {
+  pub spi: S,
+  pub timing: TimingProfile,
+  clock_rate: u32,
+  max_transfer_size: usize,
+  bitstream: Vec<u8>,
+  waveform: Waveform,
 }
 
-struct SpiPulseAccumulator {
-  current_state: Option<PinState>,
-  current_duration: Option<u32>,
-  pulses: Vec<Pulse>,
+impl<S> fmt::Debug for SpiSender<S>
+where
+  S: fmt::Debug,
+{
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("SpiSender")
+      .field("spi", &self.spi)
+      .field("timing", &self.timing)
+      .field("clock_rate", &self.clock_rate)
+      .field("max_transfer_size", &self.max_transfer_size)
+      .finish()
+  }
 }
 
-impl SpiPulseAccumulator {
-  pub const fn new() -> Self {
-    Self { current_state: None, current_duration: None, pulses: Vec::new() }
-  }
+impl<S> SpiSender<S> {
+  /// Create a new `SpiSender` for an SPI bus running at the given `clock_rate` in Hz.
+  pub fn new(spi: S, clock_rate: u32) -> Self {
+    assert!(clock_rate > 0, "clock rate must not be zero");
 
-  pub fn finish(mut self) -> Vec<Pulse> {
-    if let Some(state) = self.current_state.take() {
-      let duration = self.current_duration.take().unwrap_or(0);
-      self.pulses.push(Pulse { state, duration });
+    Self {
+      spi,
+      timing: TimingProfile::SOMFY,
+      clock_rate,
+      max_transfer_size: usize::MAX,
+      bitstream: Vec::new(),
+      waveform: Waveform::new(),
     }
+  }
 
-    self.pulses
+  pub fn clock_rate(&self) -> u32 {
+    self.clock_rate
   }
-}
 
-impl ErrorType for SpiPulseAccumulator {
-  type Error = core::convert::Infallible;
+  /// Limit the size of a single transfer in bytes, e.g. to the buffer size of the SPI driver.
+  ///
+  /// Longer bitstreams are split into multiple transfers, preferably during long low periods
+  /// like the wake-up and inter-frame gaps, where a short delay between transfers does not matter.
+  pub fn max_transfer_size(&mut self, max_transfer_size: usize) -> &mut Self {
+    assert!(max_transfer_size > 0, "maximum transfer size must not be zero");
+
+    self.max_transfer_size = max_transfer_size;
+    self
+  }
+
+  /// Quantize the `Waveform` into a bitstream, sent MSB first.
+  pub fn bitstream(&self, waveform: &Waveform) -> Vec<u8> {
+    let mut bitstream = Vec::new();
+    encode(&mut bitstream, waveform, self.clock_rate);
+    bitstream
+  }
 }
 
-impl OutputPin for SpiPulseAccumulator {
-  fn set_low(&mut self) -> Result<(), Self::Error> {
-    self.set_state(PinState::Low)
+impl<S, E> SendFrame for SpiSender<S>
+where
+  S: SpiBus<u8, Error = E>,
+{
+  type Error = E;
+
+  fn timing(&self) -> TimingProfile {
+    self.timing
   }
 
-  fn set_high(&mut self) -> Result<(), Self::Error> {
-    self.set_state(PinState::High)
+  /// Send a `Frame` once.
+  fn send_frame(&mut self, frame: &Frame) -> Result<(), Self::Error> {
+    self.send_frame_repeat(frame, 0)
   }
 
-  fn set_state(&mut self, new_state: PinState) -> Result<(), Self::Error> {
-    match self.current_state.replace(new_state) {
-      None => (),
-      Some(old_state) if old_state == new_state => (),
-      Some(state) => {
-        if let Some(duration) = self.current_duration.take() {
-          self.pulses.push(Pulse { state, duration });
-        }
-      },
+  /// Send a `Frame` with a given number of `repetitions`. The total number sent is
+  /// `1 + repetitions`, i.e. `send_frame(…)` is the same as `send_frame_repeat(…, 0)`.
+  fn send_frame_repeat(&mut self, frame: &Frame, repetitions: usize) -> Result<(), Self::Error> {
+    self.waveform.record(frame, repetitions, &self.timing);
+    encode(&mut self.bitstream, &self.waveform, self.clock_rate);
+
+    let mut offset = 0;
+    while offset < self.bitstream.len() {
+      let len = transfer_len(&self.bitstream[offset..], self.max_transfer_size);
+      self.spi.write(&self.bitstream[offset..(offset + len)])?;
+      offset += len;
     }
 
-    Ok(())
+    self.spi.flush()
   }
 }
 
-impl DelayNs for SpiPulseAccumulator {
-  fn delay_ns(&mut self, ns: u32) {
-    if let Some(ref mut current_duration) = self.current_duration {
-      *current_duration += ns;
-    } else {
-      self.current_duration = Some(ns);
-    }
+fn encode(bitstream: &mut Vec<u8>, waveform: &Waveform, clock_rate: u32) {
+  bitstream.clear();
+
+  let mut elapsed = 0;
+  let mut bits = 0;
+
+  for pulse in waveform.pulses() {
+    elapsed += u64::from(pulse.duration);
+
+    // Quantize the end of each pulse instead of its duration so rounding errors do not accumulate.
+    let end = (elapsed * u64::from(clock_rate) + 500_000) / 1_000_000;
+    let count = end - bits;
+    push_bits(bitstream, bits, count, pulse.state);
+    bits = end;
   }
 }
 
-struct OutputPinDelayProxy<'a> {
-  spi_sender: &'a RefCell<SpiPulseAccumulator>,
-}
+// Split long bitstreams in the middle of the longest low period.
+fn transfer_len(bitstream: &[u8], max_transfer_size: usize) -> usize {
+  if bitstream.len() <= max_transfer_size {
+    return bitstream.len()
+  }
+
+  let mut longest = (0, 0);
+  let mut start = 0;
+  for (i, &byte) in bitstream[..max_transfer_size].iter().enumerate() {
+    if byte != 0x00 {
+      start = i + 1;
+    } else if i + 1 - start > longest.1 {
+      longest = (start, i + 1 - start);
+    }
+  }
 
-impl<'a> OutputPinDelayProxy<'a> {
-  pub const fn new(spi_sender: &'a RefCell<SpiPulseAccumulator>) -> Self {
-    Self { spi_sender }
+  match longest {
+    (start, len) if len >= 2 => start + len / 2,
+    _ => max_transfer_size,
   }
 }
 
-impl ErrorType for OutputPinDelayProxy<'_> {
-  type Error = core::convert::Infallible;
-}
+fn push_bits(bitstream: &mut Vec<u8>, offset: u64, mut count: u64, state: PinState) {
+  let fill = match state {
+    PinState::High => 0xFF,
+    PinState::Low => 0x00,
+  };
 
-impl OutputPin for OutputPinDelayProxy<'_> {
-  fn set_low(&mut self) -> Result<(), Self::Error> {
-    self.spi_sender.borrow_mut().set_low()
+  // Fill up the last partial byte.
+  let used = (offset % 8) as u32;
+  if used != 0 && count > 0 {
+    let n = count.min(u64::from(8 - used)) as u32;
+    let mask = (0xFFu8 >> used) & !(0xFFu8.checked_shr(used + n).unwrap_or(0));
+    let last = bitstream.last_mut().unwrap();
+    *last = (*last & !mask) | (fill & mask);
+    count -= u64::from(n);
   }
 
-  fn set_high(&mut self) -> Result<(), Self::Error> {
-    self.spi_sender.borrow_mut().set_high()
+  // Long periods are added as whole bytes.
+  let bytes = (count / 8) as usize;
+  bitstream.resize(bitstream.len() + bytes, fill);
+
+  let rest = (count % 8) as u32;
+  if rest != 0 {
+    bitstream.push(fill & !(0xFF >> rest));
   }
 }
 
-impl DelayNs for OutputPinDelayProxy<'_> {
-  fn delay_ns(&mut self, ns: u32) {
-    self.spi_sender.borrow_mut().delay_ns(ns)
+#[cfg(test)]
+mod tests {
+  use core::convert::Infallible;
+
+  use embedded_hal::spi::ErrorType;
+  use ux::u24;
+
+  use super::*;
+  use crate::Command;
+
+  const CLOCK_RATE: u32 = 100_000;
+
+  #[derive(Debug, Default)]
+  struct MockSpi {
+    transfers: Vec<Vec<u8>>,
+    // Number of calls which read from the bus, which the sender never needs.
+    reads: usize,
+    flushed: bool,
   }
-}
 
-#[derive(Debug)]
-pub struct SpiSender {}
+  impl ErrorType for MockSpi {
+    type Error = Infallible;
+  }
+
+  impl SpiBus for MockSpi {
+    fn read(&mut self, _words: &mut [u8]) -> Result<(), Self::Error> {
+      self.reads += 1;
+      Ok(())
+    }
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+      self.transfers.push(words.to_vec());
+      Ok(())
+    }
+
+    fn transfer(&mut self, _read: &mut [u8], _write: &[u8]) -> Result<(), Self::Error> {
+      self.reads += 1;
+      Ok(())
+    }
+
+    fn transfer_in_place(&mut self, _words: &mut [u8]) -> Result<(), Self::Error> {
+      self.reads += 1;
+      Ok(())
+    }
 
-impl SpiSender {
-  #[allow(clippy::new_without_default)]
-  pub const fn new() -> Self {
-    Self {}
+    fn flush(&mut self) -> Result<(), Self::Error> {
+      self.flushed = true;
+      Ok(())
+    }
   }
-}
 
-impl SendFrame for SpiSender {
-  type Error = core::convert::Infallible;
+  fn frame() -> Frame {
+    Frame::builder()
+      .key(0xA7)
+      .command(Command::Up)
+      .rolling_code(42)
+      .remote_address(u24::new(0xFFAA11))
+      .build()
+      .expect("Failed to build frame")
+  }
 
-  /// Send a `Frame` once.
-  fn send_frame(&mut self, frame: &Frame) -> Result<(), Self::Error> {
-    self.send_frame_repeat(frame, 0)
+  // Returns the state and the end of every run of equal bits in clock periods.
+  fn runs(bitstream: &[u8]) -> Vec<(PinState, u64)> {
+    let mut runs = Vec::<(PinState, u64)>::new();
+
+    for (i, byte) in bitstream.iter().enumerate() {
+      for bit in 0..8 {
+        let state = PinState::from(byte & (1 << (7 - bit)) != 0);
+        let end = (i * 8 + bit + 1) as u64;
+
+        match runs.last_mut() {
+          Some((last_state, last_end)) if *last_state == state => *last_end = end,
+          _ => runs.push((state, end)),
+        }
+      }
+    }
+
+    runs
   }
 
-  /// Send a `Frame` with a given number of `repetitions`. The total number sent is
-  /// `1 + repetitions`, i.e. `send_frame(…)` is the same as `send_frame_repeat(…, 0)`.
-  fn send_frame_repeat(&mut self, frame: &Frame, repetitions: usize) -> Result<(), Self::Error> {
-    let spi_pulse_accumulator = RefCell::new(SpiPulseAccumulator::new());
+  #[test]
+  fn test_bitstream() {
+    let mut spi_sender = SpiSender::new(MockSpi::default(), CLOCK_RATE);
+    spi_sender.send_frame_repeat(&frame(), 2).unwrap();
 
-    let mut transmitter = OutputPinDelayProxy::new(&spi_pulse_accumulator);
-    let mut delay = OutputPinDelayProxy::new(&spi_pulse_accumulator);
+    assert!(spi_sender.spi.flushed);
+    assert_eq!(spi_sender.spi.reads, 0);
+    assert_eq!(spi_sender.spi.transfers.len(), 1);
 
-    let mut sender = Sender { transmitter: &mut transmitter, delay: &mut delay };
-    sender.send_frame_repeat(frame, repetitions)?;
+    let waveform = Waveform::for_frame(&frame(), 2);
+    let runs = runs(&spi_sender.spi.transfers[0]);
 
-    let pulses = spi_pulse_accumulator.into_inner().finish();
+    // The trailing inter-frame gap is merged with the padding of the last byte.
+    assert_eq!(runs.len(), waveform.pulses().len());
 
-    for pulse in pulses {
-      println!("{:<4} {:>10}", format!("{:?}", pulse.state), pulse.duration);
+    let mut elapsed = 0;
+    for (i, (pulse, &(state, end))) in waveform.pulses().iter().zip(&runs).enumerate() {
+      elapsed += u64::from(pulse.duration);
+      let expected_end = (elapsed * u64::from(CLOCK_RATE) + 500_000) / 1_000_000;
+
+      assert_eq!(pulse.state, state);
+
+      if i + 1 < runs.len() {
+        assert_eq!(end, expected_end);
+      } else {
+        assert_eq!(end, expected_end.next_multiple_of(8));
+      }
     }
+  }
+
+  #[test]
+  fn test_max_transfer_size() {
+    let mut spi_sender = SpiSender::new(MockSpi::default(), CLOCK_RATE);
+    spi_sender.max_transfer_size(1024);
+    spi_sender.send_frame_repeat(&frame(), 2).unwrap();
 
-    Ok(())
+    assert_eq!(spi_sender.spi.reads, 0);
+    let transfers = &spi_sender.spi.transfers;
+    assert!(transfers.len() > 1);
+    assert!(transfers.iter().all(|transfer| transfer.len() <= 1024));
+
+    let bitstream = spi_sender.bitstream(&Waveform::for_frame(&frame(), 2));
+    assert_eq!(transfers.concat(), bitstream);
+
+    // Transfers are split during low periods.
+    for transfer in &transfers[..(transfers.len() - 1)] {
+      assert_eq!(transfer.last(), Some(&0x00));
+    }
+    for transfer in &transfers[1..] {
+      assert_eq!(transfer.first(), Some(&0x00));
+    }
   }
 }
@@ -0,0 +1,720 @@
+use std::{
+  collections::BTreeMap,
+  fmt,
+  path::Path,
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{params, Connection, OptionalExtension, Row, Transaction, TransactionBehavior};
+use ux::u24;
+
+use crate::{Command, DeviceType, FileStorage, Frame, KeyStrategy, Remote, RemoteConfig, RollingCodeStorage};
+
+/// An error returned by `SqliteStorage`.
+#[derive(Debug)]
+pub enum SqliteStorageError {
+  /// A database operation failed.
+  Sqlite(rusqlite::Error),
+  /// A stored value could not be converted, e.g. an unknown key strategy.
+  InvalidData(String),
+  /// No remote with the given name exists.
+  UnknownRemote(String),
+  /// No remote with the given address exists.
+  UnknownAddress(u24),
+  /// A remote with the given name already exists.
+  DuplicateName(String),
+  /// A remote with the given address already exists.
+  DuplicateAddress(u24),
+}
+
+impl fmt::Display for SqliteStorageError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Sqlite(err) => err.fmt(f),
+      Self::InvalidData(message) => write!(f, "Invalid data in database: {message}"),
+      Self::UnknownRemote(name) => write!(f, "No remote with name “{name}” found"),
+      Self::UnknownAddress(address) => write!(f, "No remote with address {address} found"),
+      Self::DuplicateName(name) => write!(f, "Remote “{name}” already exists"),
+      Self::DuplicateAddress(address) => write!(f, "A remote with address {address} already exists"),
+    }
+  }
+}
+
+impl std::error::Error for SqliteStorageError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Self::Sqlite(err) => Some(err),
+      _ => None,
+    }
+  }
+}
+
+impl From<rusqlite::Error> for SqliteStorageError {
+  fn from(err: rusqlite::Error) -> Self {
+    Self::Sqlite(err)
+  }
+}
+
+/// A frame sent by a `SqliteStorage` remote, as recorded in its history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transmission {
+  pub timestamp: SystemTime,
+  pub address: u24,
+  pub command: Command,
+  pub rolling_code: u16,
+  pub repetitions: usize,
+  /// Whether the frame was transmitted successfully.
+  pub sent: bool,
+}
+
+// Each migration upgrades the database by one version, tracked in `PRAGMA user_version`.
+const MIGRATIONS: [&str; 2] = [
+  "
+    CREATE TABLE IF NOT EXISTS remotes (
+      name TEXT PRIMARY KEY NOT NULL,
+      address INTEGER NOT NULL UNIQUE,
+      rolling_code INTEGER NOT NULL,
+      key_strategy TEXT NOT NULL,
+      key INTEGER,
+      venetian INTEGER NOT NULL DEFAULT 0,
+      open_time INTEGER,
+      close_time INTEGER,
+      my_position INTEGER
+    );
+
+    CREATE TABLE IF NOT EXISTS transmissions (
+      id INTEGER PRIMARY KEY,
+      timestamp INTEGER NOT NULL,
+      address INTEGER NOT NULL,
+      command TEXT NOT NULL,
+      rolling_code INTEGER NOT NULL,
+      repetitions INTEGER NOT NULL,
+      result TEXT NOT NULL
+    );
+  ",
+  "
+    ALTER TABLE remotes ADD COLUMN device TEXT NOT NULL DEFAULT 'roller_shutter';
+    UPDATE remotes SET device = 'venetian_blind' WHERE venetian;
+    ALTER TABLE remotes DROP COLUMN venetian;
+    ALTER TABLE remotes ADD COLUMN room TEXT;
+    ALTER TABLE remotes ADD COLUMN repetitions INTEGER;
+    ALTER TABLE remotes ADD COLUMN transmitter_pin INTEGER;
+  ",
+];
+
+/// A `RollingCodeStorage` keeping all remotes in an SQLite database.
+///
+/// Every change is written in a transaction, so the database can safely be shared between
+/// multiple processes. Additionally, every transmitted frame is appended to a history table.
+#[derive(Debug)]
+pub struct SqliteStorage {
+  connection: Connection,
+  reservation_size: u16,
+  address_map: BTreeMap<u24, String>,
+  remotes: BTreeMap<String, RemoteConfig>,
+  /// Rolling codes as last read or persisted by this process, to detect codes used by other processes.
+  rolling_codes: BTreeMap<u24, u16>,
+}
+
+impl SqliteStorage {
+  /// Open the database at `path`, creating it if it does not exist yet.
+  pub fn open(path: impl AsRef<Path>) -> Result<Self, SqliteStorageError> {
+    Self::with_connection(Connection::open(path)?)
+  }
+
+  /// Open a temporary in-memory database.
+  pub fn open_in_memory() -> Result<Self, SqliteStorageError> {
+    Self::with_connection(Connection::open_in_memory()?)
+  }
+
+  fn with_connection(connection: Connection) -> Result<Self, SqliteStorageError> {
+    connection.busy_timeout(Duration::from_secs(5))?;
+
+    let mut storage = Self {
+      connection,
+      reservation_size: 1,
+      address_map: BTreeMap::new(),
+      remotes: BTreeMap::new(),
+      rolling_codes: BTreeMap::new(),
+    };
+    storage.migrate()?;
+    storage.reload()?;
+    storage.rolling_codes =
+      storage.remotes.values().map(|config| (config.remote.address(), config.remote.rolling_code())).collect();
+
+    Ok(storage)
+  }
+
+  /// Import all remotes from a `FileStorage` which do not exist in the database yet.
+  ///
+  /// Returns the number of imported remotes.
+  pub fn import(&mut self, storage: &FileStorage) -> Result<usize, SqliteStorageError> {
+    let imported = self.update(|transaction| {
+      let mut imported = 0;
+
+      for (name, config) in storage.remotes() {
+        let exists = transaction
+          .query_row(
+            "SELECT 1 FROM remotes WHERE name = ?1 OR address = ?2",
+            params![name, u32::from(config.remote.address())],
+            |_| Ok(()),
+          )
+          .optional()?;
+
+        if exists.is_none() {
+          insert_remote(transaction, name, config)?;
+          imported += 1;
+        }
+      }
+
+      Ok(imported)
+    })?;
+
+    for config in self.remotes.values() {
+      self.rolling_codes.entry(config.remote.address()).or_insert(config.remote.rolling_code());
+    }
+
+    Ok(imported)
+  }
+
+  /// Set the number of rolling codes reserved with a single write.
+  pub fn reserve(&mut self, reservation_size: u16) -> &mut Self {
+    self.reservation_size = reservation_size;
+    self
+  }
+
+  pub fn remote(&self, name: &str) -> Option<&Remote> {
+    self.remotes.get(name).map(|config| &config.remote)
+  }
+
+  pub fn remote_config(&self, name: &str) -> Option<&RemoteConfig> {
+    self.remotes.get(name)
+  }
+
+  pub fn remote_name(&self, address: u24) -> Option<&str> {
+    self.address_map.get(&address).map(|name| name.as_str())
+  }
+
+  /// Add a new remote to the database.
+  pub fn add_remote(&mut self, name: String, remote: Remote) -> Result<(), SqliteStorageError> {
+    self.update(|transaction| {
+      let exists = transaction.query_row("SELECT 1 FROM remotes WHERE name = ?1", [&name], |_| Ok(())).optional()?;
+      if exists.is_some() {
+        return Err(SqliteStorageError::DuplicateName(name))
+      }
+
+      let exists = transaction
+        .query_row("SELECT 1 FROM remotes WHERE address = ?1", [u32::from(remote.address())], |_| Ok(()))
+        .optional()?;
+      if exists.is_some() {
+        return Err(SqliteStorageError::DuplicateAddress(remote.address()))
+      }
+
+      insert_remote(transaction, &name, &remote.into())
+    })
+  }
+
+  /// Remove a remote from the database, keeping its history.
+  pub fn remove_remote(&mut self, name: &str) -> Result<Option<Remote>, SqliteStorageError> {
+    self.reload()?;
+    let remote = self.remotes.get(name).map(|config| config.remote.clone());
+
+    let removed =
+      self.update(|transaction| Ok(transaction.execute("DELETE FROM remotes WHERE name = ?1", [name])? > 0))?;
+    Ok(remote.filter(|_| removed))
+  }
+
+  /// Rename a remote, keeping its history.
+  pub fn rename_remote(&mut self, name: &str, new_name: String) -> Result<(), SqliteStorageError> {
+    self.update(|transaction| {
+      let exists =
+        transaction.query_row("SELECT 1 FROM remotes WHERE name = ?1", [&new_name], |_| Ok(())).optional()?;
+      if exists.is_some() {
+        return Err(SqliteStorageError::DuplicateName(new_name))
+      }
+
+      let updated = transaction.execute("UPDATE remotes SET name = ?2 WHERE name = ?1", params![name, new_name])?;
+      if updated == 0 {
+        return Err(SqliteStorageError::UnknownRemote(name.to_owned()))
+      }
+
+      Ok(())
+    })
+  }
+
+  /// Change the address of a remote.
+  ///
+  /// Motors paired with the old address no longer react to the remote.
+  pub fn set_address(&mut self, name: &str, address: u24) -> Result<(), SqliteStorageError> {
+    let old_address = self.update(|transaction| {
+      let other = transaction
+        .query_row("SELECT name FROM remotes WHERE address = ?1", [u32::from(address)], |row| row.get::<_, String>(0))
+        .optional()?;
+      if other.is_some_and(|other| other != name) {
+        return Err(SqliteStorageError::DuplicateAddress(address))
+      }
+
+      let old_address = transaction
+        .query_row("SELECT address FROM remotes WHERE name = ?1", [name], |row| row.get::<_, u32>(0))
+        .optional()?
+        .ok_or_else(|| SqliteStorageError::UnknownRemote(name.to_owned()))?;
+      transaction.execute("UPDATE remotes SET address = ?2 WHERE name = ?1", params![name, u32::from(address)])?;
+
+      Ok(u24::new(old_address))
+    })?;
+
+    if let Some(rolling_code) = self.rolling_codes.remove(&old_address) {
+      self.rolling_codes.insert(address, rolling_code);
+    }
+
+    Ok(())
+  }
+
+  /// Set the rolling code of a remote, e.g. after restoring a backup.
+  pub fn set_rolling_code(&mut self, name: &str, rolling_code: u16) -> Result<(), SqliteStorageError> {
+    self.update(|transaction| {
+      let updated =
+        transaction.execute("UPDATE remotes SET rolling_code = ?2 WHERE name = ?1", params![name, rolling_code])?;
+      if updated == 0 {
+        return Err(SqliteStorageError::UnknownRemote(name.to_owned()))
+      }
+
+      Ok(())
+    })?;
+
+    if let Some(remote) = self.remote(name) {
+      self.rolling_codes.insert(remote.address(), rolling_code);
+    }
+
+    Ok(())
+  }
+
+  /// Set the favourite position of a remote.
+  pub fn set_my_position(&mut self, name: &str, my_position: Option<u8>) -> Result<(), SqliteStorageError> {
+    self.update(|transaction| {
+      let updated =
+        transaction.execute("UPDATE remotes SET my_position = ?2 WHERE name = ?1", params![name, my_position])?;
+      if updated == 0 {
+        return Err(SqliteStorageError::UnknownRemote(name.to_owned()))
+      }
+
+      Ok(())
+    })
+  }
+
+  /// Set the travel times of a remote, rounded to milliseconds.
+  pub fn set_travel_times(
+    &mut self,
+    name: &str,
+    open_time: Duration,
+    close_time: Duration,
+  ) -> Result<(), SqliteStorageError> {
+    let millis = |duration: Duration| (duration.as_micros() as u64 + 500) / 1000;
+
+    self.update(|transaction| {
+      let updated = transaction.execute(
+        "UPDATE remotes SET open_time = ?2, close_time = ?3 WHERE name = ?1",
+        params![name, millis(open_time), millis(close_time)],
+      )?;
+      if updated == 0 {
+        return Err(SqliteStorageError::UnknownRemote(name.to_owned()))
+      }
+
+      Ok(())
+    })
+  }
+
+  /// Pick up rolling codes used by other processes for the given `remote`.
+  ///
+  /// If the rolling code in the database changed since it was last read or written by this
+  /// process, `remote` is replaced with the stored one, dropping any reserved rolling codes.
+  pub fn refresh(&mut self, remote: &mut Remote) -> Result<(), SqliteStorageError> {
+    self.reload()?;
+
+    let Some(stored) = self.address_map.get(&remote.address()).map(|name| self.remotes[name].remote.clone()) else {
+      return Ok(())
+    };
+
+    if self
+      .rolling_codes
+      .insert(remote.address(), stored.rolling_code())
+      .is_some_and(|known| known != stored.rolling_code())
+    {
+      log::warn!("Rolling code of remote {} was changed by another process.", remote.address());
+      *remote = stored;
+    }
+
+    Ok(())
+  }
+
+  /// Generate a random address which is not used by any remote yet.
+  pub fn unused_address(&self) -> u24 {
+    crate::unused_address(|address| self.address_map.contains_key(&address))
+  }
+
+  pub fn remotes(&self) -> &BTreeMap<String, RemoteConfig> {
+    &self.remotes
+  }
+
+  /// The last `limit` transmissions of the remote with the given `address`, newest first.
+  pub fn history(&self, address: u24, limit: usize) -> Result<Vec<Transmission>, SqliteStorageError> {
+    let mut statement = self.connection.prepare(
+      "SELECT timestamp, address, command, rolling_code, repetitions, result FROM transmissions
+        WHERE address = ?1 ORDER BY id DESC LIMIT ?2",
+    )?;
+
+    let rows = statement.query_map(params![u32::from(address), limit as i64], |row| {
+      Ok((
+        row.get::<_, u64>(0)?,
+        row.get::<_, u32>(1)?,
+        row.get::<_, String>(2)?,
+        row.get::<_, u16>(3)?,
+        row.get::<_, i64>(4)?,
+        row.get::<_, String>(5)?,
+      ))
+    })?;
+
+    rows
+      .map(|row| {
+        let (timestamp, address, command, rolling_code, repetitions, result) = row?;
+
+        Ok(Transmission {
+          timestamp: UNIX_EPOCH + Duration::from_millis(timestamp),
+          address: u24::new(address),
+          command: command
+            .parse()
+            .map_err(|_| SqliteStorageError::InvalidData(format!("unknown command “{command}”")))?,
+          rolling_code,
+          repetitions: repetitions as usize,
+          sent: result == "sent",
+        })
+      })
+      .collect()
+  }
+}
+
+impl SqliteStorage {
+  fn migrate(&mut self) -> Result<(), SqliteStorageError> {
+    let transaction = self.connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let version = transaction.query_row("PRAGMA user_version", [], |row| row.get::<_, usize>(0))?;
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+      log::info!("Upgrading database to version {}.", i + 1);
+      transaction.execute_batch(migration)?;
+      transaction.pragma_update(None, "user_version", i + 1)?;
+    }
+
+    Ok(transaction.commit()?)
+  }
+
+  // Run `f` in a transaction and re-read all remotes before committing it.
+  fn update<T>(
+    &mut self,
+    f: impl FnOnce(&Transaction<'_>) -> Result<T, SqliteStorageError>,
+  ) -> Result<T, SqliteStorageError> {
+    let transaction = self.connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+    let result = f(&transaction)?;
+    let remotes = load_remotes(&transaction)?;
+    transaction.commit()?;
+
+    self.set_remotes(remotes);
+    Ok(result)
+  }
+
+  fn reload(&mut self) -> Result<(), SqliteStorageError> {
+    let remotes = load_remotes(&self.connection)?;
+    self.set_remotes(remotes);
+    Ok(())
+  }
+
+  fn set_remotes(&mut self, remotes: BTreeMap<String, RemoteConfig>) {
+    self.address_map = remotes.iter().map(|(k, v)| (v.remote.address(), k.to_owned())).collect();
+    self.remotes = remotes;
+  }
+}
+
+impl RollingCodeStorage for SqliteStorage {
+  type Error = SqliteStorageError;
+
+  fn persist(&mut self, remote: &Remote) -> Result<(), Self::Error> {
+    log::info!("Persisting rolling code for remote {}.", remote.address());
+
+    let known = self.rolling_codes.insert(remote.address(), remote.rolling_code());
+
+    self.update(|transaction| {
+      let address = u32::from(remote.address());
+      let Some(stored) = transaction
+        .query_row("SELECT rolling_code FROM remotes WHERE address = ?1", [address], |row| row.get::<_, u16>(0))
+        .optional()?
+      else {
+        return Err(SqliteStorageError::UnknownAddress(remote.address()))
+      };
+
+      // If another process used rolling codes in the meantime, never move back behind them.
+      let mut rolling_code = remote.rolling_code();
+      let behind = rolling_code.wrapping_sub(stored) > u16::MAX / 2;
+      if known.is_some_and(|known| known != stored) && behind {
+        log::warn!("Rolling code of remote {} was advanced by another process.", remote.address());
+        rolling_code = stored;
+      }
+
+      let (key_strategy, key) = key_to_sql(remote.key());
+      transaction.execute(
+        "UPDATE remotes SET rolling_code = ?2, key_strategy = ?3, key = ?4 WHERE address = ?1",
+        params![address, rolling_code, key_strategy, key],
+      )?;
+
+      Ok(())
+    })
+  }
+
+  fn reservation_size(&self) -> u16 {
+    self.reservation_size
+  }
+
+  fn record_transmission(&mut self, frame: &Frame, repetitions: usize, sent: bool) -> Result<(), Self::Error> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+    let command = format!("{:?}", frame.command()).to_lowercase();
+
+    self.connection.execute(
+      "INSERT INTO transmissions (timestamp, address, command, rolling_code, repetitions, result)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+      params![
+        timestamp,
+        u32::from(frame.remote_address()),
+        command,
+        frame.rolling_code(),
+        repetitions as i64,
+        if sent { "sent" } else { "failed" },
+      ],
+    )?;
+
+    Ok(())
+  }
+}
+
+fn key_to_sql(key: KeyStrategy) -> (&'static str, Option<u8>) {
+  match key {
+    KeyStrategy::Fixed(key) => ("fixed", Some(key)),
+    KeyStrategy::Rolling => ("rolling", None),
+    KeyStrategy::RollingWithBase(base) => ("rolling_with_base", Some(base)),
+  }
+}
+
+fn device_to_sql(device: DeviceType) -> &'static str {
+  match device {
+    DeviceType::RollerShutter => "roller_shutter",
+    DeviceType::VenetianBlind => "venetian_blind",
+    DeviceType::Awning => "awning",
+  }
+}
+
+fn device_from_sql(device: &str) -> Option<DeviceType> {
+  Some(match device {
+    "roller_shutter" => DeviceType::RollerShutter,
+    "venetian_blind" => DeviceType::VenetianBlind,
+    "awning" => DeviceType::Awning,
+    _ => return None,
+  })
+}
+
+fn key_from_sql(key_strategy: &str, key: Option<u8>) -> Option<KeyStrategy> {
+  Some(match key_strategy {
+    "fixed" => KeyStrategy::Fixed(key?),
+    "rolling" => KeyStrategy::Rolling,
+    "rolling_with_base" => KeyStrategy::RollingWithBase(key?),
+    _ => return None,
+  })
+}
+
+fn insert_remote(connection: &Connection, name: &str, config: &RemoteConfig) -> Result<(), SqliteStorageError> {
+  let (key_strategy, key) = key_to_sql(config.remote.key());
+  let millis = |duration: Option<Duration>| duration.map(|duration| duration.as_millis() as u64);
+
+  connection.execute(
+    "INSERT INTO remotes (name, address, rolling_code, key_strategy, key, device, room, open_time, close_time,
+        my_position, repetitions, transmitter_pin)
+      VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+    params![
+      name,
+      u32::from(config.remote.address()),
+      config.remote.rolling_code(),
+      key_strategy,
+      key,
+      device_to_sql(config.device),
+      config.room,
+      millis(config.open_time),
+      millis(config.close_time),
+      config.my_position,
+      config.repetitions.map(|repetitions| repetitions as i64),
+      config.transmitter_pin,
+    ],
+  )?;
+
+  Ok(())
+}
+
+fn load_remotes(connection: &Connection) -> Result<BTreeMap<String, RemoteConfig>, SqliteStorageError> {
+  let mut statement = connection.prepare(
+    "SELECT name, address, rolling_code, key_strategy, key, device, room, open_time, close_time, my_position,
+      repetitions, transmitter_pin FROM remotes",
+  )?;
+
+  let remote = |row: &Row<'_>| {
+    let millis = |index| row.get::<_, Option<u64>>(index).map(|millis| millis.map(Duration::from_millis));
+
+    Ok((
+      row.get::<_, String>(0)?,
+      row.get::<_, u32>(1)?,
+      row.get::<_, u16>(2)?,
+      row.get::<_, String>(3)?,
+      row.get::<_, Option<u8>>(4)?,
+      row.get::<_, String>(5)?,
+      RemoteConfig {
+        remote: Remote::new(u24::new(0), 0),
+        device: DeviceType::default(),
+        room: row.get(6)?,
+        open_time: millis(7)?,
+        close_time: millis(8)?,
+        my_position: row.get(9)?,
+        repetitions: row.get::<_, Option<i64>>(10)?.map(|repetitions| repetitions as usize),
+        transmitter_pin: row.get(11)?,
+      },
+    ))
+  };
+
+  let rows = statement.query_map([], remote)?;
+
+  rows
+    .map(|row| {
+      let (name, address, rolling_code, key_strategy, key, device, mut config) = row?;
+
+      let key = key_from_sql(&key_strategy, key)
+        .ok_or_else(|| SqliteStorageError::InvalidData(format!("invalid key strategy for remote “{name}”")))?;
+      config.remote = Remote::new(u24::new(address), rolling_code).with_key(key);
+      config.device = device_from_sql(&device)
+        .ok_or_else(|| SqliteStorageError::InvalidData(format!("invalid device type for remote “{name}”")))?;
+
+      Ok((name, config))
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs;
+
+  use super::*;
+
+  #[test]
+  fn test_sqlite_storage() {
+    let mut storage = SqliteStorage::open_in_memory().unwrap();
+    storage.reserve(10);
+
+    storage.add_remote(String::from("Remote A"), Remote::new(u24::new(170), 1).with_key(KeyStrategy::Rolling)).unwrap();
+    assert!(matches!(
+      storage.add_remote(String::from("Remote A"), Remote::new(u24::new(171), 1)),
+      Err(SqliteStorageError::DuplicateName(_))
+    ));
+    assert!(matches!(
+      storage.add_remote(String::from("Remote B"), Remote::new(u24::new(170), 1)),
+      Err(SqliteStorageError::DuplicateAddress(_))
+    ));
+
+    storage.set_travel_times("Remote A", Duration::from_secs(20), Duration::from_millis(17500)).unwrap();
+    storage.set_my_position("Remote A", Some(40)).unwrap();
+    assert!(storage.set_my_position("Remote B", Some(40)).is_err());
+
+    let mut remote = storage.remote("Remote A").unwrap().clone();
+    assert_eq!(remote.reserve_rolling_code(&mut storage).unwrap(), 1);
+    assert_eq!(storage.remote("Remote A").unwrap().rolling_code(), 11);
+
+    remote.commit(&mut storage).unwrap();
+    let config = storage.remote_config("Remote A").unwrap();
+    assert_eq!(config.remote.rolling_code(), 2);
+    assert_eq!(config.remote.key(), KeyStrategy::Rolling);
+    assert_eq!(config.close_time, Some(Duration::from_millis(17500)));
+    assert_eq!(config.my_position, Some(40));
+
+    storage.add_remote(String::from("Remote B"), Remote::new(u24::new(171), 1)).unwrap();
+    storage.rename_remote("Remote A", String::from("Remote C")).unwrap();
+    assert!(matches!(
+      storage.rename_remote("Remote B", String::from("Remote C")),
+      Err(SqliteStorageError::DuplicateName(_))
+    ));
+    storage.set_address("Remote C", u24::new(172)).unwrap();
+    assert!(matches!(storage.set_address("Remote C", u24::new(171)), Err(SqliteStorageError::DuplicateAddress(_))));
+    storage.set_rolling_code("Remote C", 100).unwrap();
+    assert_eq!(storage.remote_name(u24::new(172)), Some("Remote C"));
+    assert_eq!(storage.remote("Remote C").unwrap().rolling_code(), 100);
+
+    assert!(storage.remove_remote("Remote B").unwrap().is_some());
+    assert!(storage.remove_remote("Remote C").unwrap().is_some());
+    assert!(storage.remove_remote("Remote C").unwrap().is_none());
+    assert!(storage.remotes().is_empty());
+  }
+
+  #[test]
+  fn test_history() {
+    let mut storage = SqliteStorage::open_in_memory().unwrap();
+
+    let frame =
+      Frame::builder().key(0xA7).command(Command::MyUp).remote_address(u24::new(170)).rolling_code(42).build().unwrap();
+    storage.record_transmission(&frame, 3, true).unwrap();
+    storage.record_transmission(&frame, 0, false).unwrap();
+
+    let history = storage.history(u24::new(170), 10).unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!((history[0].command, history[0].repetitions, history[0].sent), (Command::MyUp, 0, false));
+    assert_eq!((history[1].rolling_code, history[1].repetitions, history[1].sent), (42, 3, true));
+    assert!(storage.history(u24::new(171), 10).unwrap().is_empty());
+  }
+
+  #[test]
+  fn test_import() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.yaml");
+
+    fs::write(
+      &path,
+      "Remote A:\n  address: 170\n  rolling_code: 5\n  key: !rolling_with_base 176\n  venetian: true\nRemote B:\n  address: 171\n  rolling_code: 2\n",
+    )
+    .unwrap();
+    let file_storage = FileStorage::open(&path).unwrap();
+
+    let path = dir.path().join("somfy.db");
+    let mut storage = SqliteStorage::open(&path).unwrap();
+    storage.add_remote(String::from("Remote B"), Remote::new(u24::new(171), 9)).unwrap();
+    assert_eq!(storage.import(&file_storage).unwrap(), 1);
+
+    let storage = SqliteStorage::open(&path).unwrap();
+    let config = storage.remote_config("Remote A").unwrap();
+    assert_eq!(config.remote.rolling_code(), 5);
+    assert_eq!(config.remote.key(), KeyStrategy::RollingWithBase(176));
+    assert!(config.is_venetian());
+    assert_eq!(storage.remote("Remote B").unwrap().rolling_code(), 9);
+  }
+
+  #[test]
+  fn test_migrate() {
+    let connection = Connection::open_in_memory().unwrap();
+    connection.execute_batch(MIGRATIONS[0]).unwrap();
+    connection.pragma_update(None, "user_version", 1).unwrap();
+    connection
+      .execute(
+        "INSERT INTO remotes (name, address, rolling_code, key_strategy, venetian) VALUES ('Remote', 170, 5, 'rolling', 1)",
+        [],
+      )
+      .unwrap();
+
+    let storage = SqliteStorage::with_connection(connection).unwrap();
+    let config = storage.remote_config("Remote").unwrap();
+    assert_eq!(config.device, DeviceType::VenetianBlind);
+    assert_eq!(config.remote.rolling_code(), 5);
+    assert_eq!(config.repetitions, None);
+
+    let version: usize = storage.connection.query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap();
+    assert_eq!(version, MIGRATIONS.len());
+  }
+}
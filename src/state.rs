@@ -0,0 +1,196 @@
+use std::{
+  collections::BTreeMap,
+  fs::File,
+  io,
+  path::{Path, PathBuf},
+  time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+
+use somfy::{
+  file_io::{lock_file, write_file},
+  Command, PositionEstimator, RemoteConfig,
+};
+
+/// The last known state of a blind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlindState {
+  /// Estimated position in percent at the time of the last command, if known.
+  pub position: Option<u8>,
+  /// Position in percent at which the blind stops by itself, if it was moving at the time of the last command.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub moving_to: Option<u8>,
+  pub last_command: Option<Command>,
+  #[serde(with = "humantime_serde")]
+  pub timestamp: SystemTime,
+}
+
+/// State of all blinds, kept separate from the config so frequent writes do not touch the rolling codes.
+#[derive(Debug)]
+pub struct StateFile {
+  path: PathBuf,
+  blinds: BTreeMap<String, BlindState>,
+}
+
+impl StateFile {
+  /// Open the state file at the given `path`, starting with an empty state if it does not exist yet.
+  pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+    let mut state = Self { path: path.as_ref().into(), blinds: BTreeMap::new() };
+    state.reload()?;
+
+    Ok(state)
+  }
+
+  #[cfg(test)]
+  pub fn get(&self, name: &str) -> Option<&BlindState> {
+    self.blinds.get(name)
+  }
+
+  /// The current position of a blind in percent, estimated from its last state.
+  pub fn position(&self, name: &str, config: &RemoteConfig) -> Option<u8> {
+    let (estimator, now) = self.estimator(name, config)?;
+    Some(estimator.position(now))
+  }
+
+  /// Record that `command` was sent to a blind and write the state file.
+  ///
+  /// The new state is estimated from the last state, so this is meant for sending single commands.
+  pub fn command(&mut self, name: &str, config: &RemoteConfig, command: Command) -> io::Result<()> {
+    let (position, moving_to) = match self.estimator(name, config) {
+      Some((mut estimator, now)) => {
+        let position = estimator.position(now);
+        estimator.command(command, now);
+        (Some(position), estimator.target(now))
+      },
+      None => {
+        let moving_to = match command {
+          Command::Up => Some(100),
+          Command::Down => Some(0),
+          Command::My => config.my_position,
+          _ => None,
+        };
+        (None, moving_to)
+      },
+    };
+
+    self.record(name, position, moving_to, command)
+  }
+
+  /// Record the `position` of a blind after `command` was sent and write the state file.
+  pub fn record(
+    &mut self,
+    name: &str,
+    position: Option<u8>,
+    moving_to: Option<u8>,
+    command: Command,
+  ) -> io::Result<()> {
+    let state = BlindState { position, moving_to, last_command: Some(command), timestamp: SystemTime::now() };
+
+    // Re-read the file under the lock, so states recorded by other processes are kept.
+    let _lock = lock_file(&self.path)?;
+    self.reload()?;
+    self.blinds.insert(name.to_owned(), state);
+    self.save()
+  }
+
+  /// Move the state of a renamed blind and write the state file.
+  pub fn rename(&mut self, name: &str, new_name: &str) -> io::Result<()> {
+    let _lock = lock_file(&self.path)?;
+    self.reload()?;
+
+    let Some(state) = self.blinds.remove(name) else { return Ok(()) };
+    self.blinds.insert(new_name.to_owned(), state);
+    self.save()
+  }
+
+  /// Forget the state of a removed blind and write the state file.
+  pub fn remove(&mut self, name: &str) -> io::Result<()> {
+    let _lock = lock_file(&self.path)?;
+    self.reload()?;
+
+    if self.blinds.remove(name).is_some() {
+      return self.save()
+    }
+
+    Ok(())
+  }
+
+  // Recreate the estimator for a blind from its last state, together with the time elapsed since.
+  fn estimator(&self, name: &str, config: &RemoteConfig) -> Option<(PositionEstimator, Duration)> {
+    let state = self.blinds.get(name)?;
+
+    // If the position was unknown, assume the blind started from the opposite end.
+    let position = match (state.position, state.moving_to) {
+      (Some(position), _) => position,
+      (None, Some(100)) => 0,
+      (None, Some(0)) => 100,
+      (None, _) => return None,
+    };
+
+    // Without travel times, the blind is assumed to reach its target immediately.
+    let mut estimator = config.estimator(position).unwrap_or_else(|| {
+      PositionEstimator::new(Duration::ZERO, Duration::ZERO, position).with_my_position(config.my_position)
+    });
+
+    if let Some(moving_to) = state.moving_to {
+      estimator.move_towards(moving_to, Duration::ZERO);
+    }
+
+    let elapsed = SystemTime::now().duration_since(state.timestamp).unwrap_or_default();
+    Some((estimator, elapsed))
+  }
+
+  fn reload(&mut self) -> io::Result<()> {
+    self.blinds = match File::open(&self.path) {
+      Ok(mut file) => serde_yaml::from_reader(&mut file).map_err(io::Error::other)?,
+      Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+      Err(err) => return Err(err),
+    };
+
+    Ok(())
+  }
+
+  fn save(&self) -> io::Result<()> {
+    let contents = serde_yaml::to_string(&self.blinds).map_err(io::Error::other)?;
+    write_file(&self.path, contents.as_bytes())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use ux::u24;
+
+  use super::*;
+  use somfy::Remote;
+
+  #[test]
+  fn test_state_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("state.yaml");
+
+    let mut config = RemoteConfig::from(Remote::new(u24::new(0xAA), 0));
+
+    let mut state = StateFile::open(&path).unwrap();
+    assert_eq!(state.position("Remote A", &config), None);
+
+    state.record("Remote A", Some(30), None, Command::My).unwrap();
+    state.command("Remote B", &config, Command::Up).unwrap();
+
+    let mut state = StateFile::open(&path).unwrap();
+    assert_eq!(state.get("Remote A").unwrap().last_command, Some(Command::My));
+    assert_eq!(state.position("Remote A", &config), Some(30));
+    assert_eq!(state.position("Remote B", &config), Some(100));
+
+    // With travel times, the blind is estimated to be somewhere in between.
+    config.open_time = Some(Duration::from_secs(3600));
+    config.close_time = Some(Duration::from_secs(3600));
+    assert_eq!(state.position("Remote B", &config), Some(0));
+
+    // A stopped blind moves to its favourite position.
+    config.my_position = Some(60);
+    state.command("Remote A", &config, Command::My).unwrap();
+    assert_eq!(state.get("Remote A").unwrap().moving_to, Some(60));
+    assert_eq!(state.position("Remote A", &config), Some(30));
+  }
+}
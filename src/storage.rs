@@ -1,96 +1,182 @@
-use std::{
-  collections::BTreeMap,
-  fs::File,
-  io,
-  path::{Path, PathBuf},
-  str,
-};
+use std::{collections::BTreeMap, fmt, path::Path, time::Duration};
 
 use ux::u24;
 
-use somfy::{Remote, RollingCodeStorage};
+use somfy::{
+  FileStorage, FileStorageError, Frame, Remote, RemoteConfig, RollingCodeStorage, Settings, SqliteStorage,
+  SqliteStorageError,
+};
+
+/// Name of the file in the state directory which holds rolling codes and other values changed at runtime.
+const REMOTE_STATE_FILE_NAME: &str = "remotes.yaml";
 
 #[derive(Debug)]
-pub struct Storage {
-  path: PathBuf,
-  address_map: BTreeMap<u24, String>,
-  remotes: BTreeMap<String, Remote>,
+pub enum StorageError {
+  File(FileStorageError),
+  Sqlite(SqliteStorageError),
 }
 
-impl Storage {
-  pub fn new(path: impl AsRef<Path>) -> io::Result<Self> {
-    let mut file = File::open(&path)?;
+impl fmt::Display for StorageError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::File(err) => err.fmt(f),
+      Self::Sqlite(err) => err.fmt(f),
+    }
+  }
+}
+
+impl std::error::Error for StorageError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Self::File(err) => err.source(),
+      Self::Sqlite(err) => err.source(),
+    }
+  }
+}
+
+impl From<FileStorageError> for StorageError {
+  fn from(err: FileStorageError) -> Self {
+    Self::File(err)
+  }
+}
 
-    match serde_yaml::from_reader::<_, BTreeMap<String, Remote>>(&mut file) {
-      Ok(remotes) => {
-        let address_map = remotes.iter().map(|(k, v)| (v.address(), k.to_owned())).collect();
+impl From<SqliteStorageError> for StorageError {
+  fn from(err: SqliteStorageError) -> Self {
+    Self::Sqlite(err)
+  }
+}
+
+/// The storage backend selected with `--storage`.
+#[derive(Debug)]
+pub enum Storage {
+  File(FileStorage),
+  Sqlite(SqliteStorage),
+}
+
+macro_rules! delegate {
+  ($self:ident, $storage:ident => $expr:expr) => {
+    match $self {
+      Self::File($storage) => $expr,
+      Self::Sqlite($storage) => $expr,
+    }
+  };
+}
 
-        Ok(Self { path: path.as_ref().into(), address_map, remotes })
-      },
-      Err(err) => Err(io::Error::new(io::ErrorKind::Other, err)),
+impl Storage {
+  /// Open the storage given by `url`, which is either `sqlite://PATH` or a config file path, defaulting to
+  /// the config file at `config_path`.
+  ///
+  /// A newly created SQLite database is populated with the remotes from the config file at `config_path`.
+  pub fn open(url: Option<&str>, config_path: &Path, state_dir: Option<&Path>) -> Result<Self, StorageError> {
+    let url = url.map(Path::new).unwrap_or(config_path);
+    let Some(path) = url.to_str().and_then(|url| url.strip_prefix("sqlite://")) else {
+      return Ok(Self::File(open_config(url, state_dir)?))
+    };
+
+    let mut storage = SqliteStorage::open(path)?;
+
+    if storage.remotes().is_empty() && config_path.exists() {
+      let imported = storage.import(&open_config(config_path, state_dir)?)?;
+      log::info!("Imported {imported} remotes from {}.", config_path.display());
     }
+
+    Ok(Self::Sqlite(storage))
+  }
+
+  /// Global settings, which are always read from the config file at `config_path`.
+  pub fn settings(&self, config_path: &Path, state_dir: Option<&Path>) -> Result<Settings, StorageError> {
+    Ok(match self {
+      Self::File(storage) => storage.settings().clone(),
+      Self::Sqlite(_) if config_path.exists() => open_config(config_path, state_dir)?.settings().clone(),
+      Self::Sqlite(_) => Settings::default(),
+    })
+  }
+
+  #[cfg(feature = "server")]
+  pub fn reserve(&mut self, reservation_size: u16) -> &mut Self {
+    delegate!(self, storage => { storage.reserve(reservation_size); });
+    self
   }
 
   pub fn remote(&self, name: &str) -> Option<&Remote> {
-    self.remotes.get(name)
+    delegate!(self, storage => storage.remote(name))
   }
 
-  #[allow(unused)]
-  pub fn remotes(&self) -> &BTreeMap<String, Remote> {
-    &self.remotes
+  pub fn remote_config(&self, name: &str) -> Option<&RemoteConfig> {
+    delegate!(self, storage => storage.remote_config(name))
   }
-}
 
-impl RollingCodeStorage for Storage {
-  type Error = io::Error;
+  pub fn remote_name(&self, address: u24) -> Option<&str> {
+    delegate!(self, storage => storage.remote_name(address))
+  }
 
-  fn persist(&mut self, remote: &Remote) -> Result<(), Self::Error> {
-    log::info!("Persisting config for remote {}.", remote.address());
+  pub fn add_remote(&mut self, name: String, remote: Remote) -> Result<(), StorageError> {
+    delegate!(self, storage => Ok(storage.add_remote(name, remote)?))
+  }
+
+  pub fn remove_remote(&mut self, name: &str) -> Result<Option<Remote>, StorageError> {
+    delegate!(self, storage => Ok(storage.remove_remote(name)?))
+  }
 
-    if let Some(remote_name) = self.address_map.get(&remote.address()) {
-      if let Some(old_remote) = self.remotes.get_mut(remote_name) {
-        *old_remote = remote.clone();
+  pub fn rename_remote(&mut self, name: &str, new_name: String) -> Result<(), StorageError> {
+    delegate!(self, storage => Ok(storage.rename_remote(name, new_name)?))
+  }
 
-        let mut file = File::create(&self.path)?;
+  pub fn set_address(&mut self, name: &str, address: u24) -> Result<(), StorageError> {
+    delegate!(self, storage => Ok(storage.set_address(name, address)?))
+  }
 
-        if let Err(err) = serde_yaml::to_writer(&mut file, &self.remotes) {
-          return Err(io::Error::new(io::ErrorKind::Other, err))
-        }
+  pub fn set_rolling_code(&mut self, name: &str, rolling_code: u16) -> Result<(), StorageError> {
+    delegate!(self, storage => Ok(storage.set_rolling_code(name, rolling_code)?))
+  }
 
-        return Ok(())
-      }
-    }
+  pub fn set_my_position(&mut self, name: &str, my_position: Option<u8>) -> Result<(), StorageError> {
+    delegate!(self, storage => Ok(storage.set_my_position(name, my_position)?))
+  }
+
+  pub fn set_travel_times(
+    &mut self,
+    name: &str,
+    open_time: Duration,
+    close_time: Duration,
+  ) -> Result<(), StorageError> {
+    delegate!(self, storage => Ok(storage.set_travel_times(name, open_time, close_time)?))
+  }
 
-    Err(io::Error::new(io::ErrorKind::NotFound, format!("No entry found for remote {}.", remote.address())))
+  #[cfg(feature = "server")]
+  pub fn refresh(&mut self, remote: &mut Remote) -> Result<(), StorageError> {
+    delegate!(self, storage => Ok(storage.refresh(remote)?))
+  }
+
+  pub fn unused_address(&self) -> u24 {
+    delegate!(self, storage => storage.unused_address())
+  }
+
+  pub fn remotes(&self) -> &BTreeMap<String, RemoteConfig> {
+    delegate!(self, storage => storage.remotes())
+  }
+}
+
+/// Open the config file at `path`, keeping it read-only if a `state_dir` is given.
+fn open_config(path: &Path, state_dir: Option<&Path>) -> Result<FileStorage, FileStorageError> {
+  match state_dir {
+    Some(state_dir) => FileStorage::open_with_state(path, state_dir.join(REMOTE_STATE_FILE_NAME)),
+    None => FileStorage::open(path),
   }
 }
 
-#[cfg(test)]
-mod tests {
-  use super::*;
-
-  #[test]
-  #[ignore]
-  fn test_storage() {
-    // let dir = tempfile::tempdir().unwrap();
-    //
-    // let mut s = Storage::new(dir.path().join("config.yaml"));
-    //
-    // s.add_remote(String::from("Remote A"), u24::new(0xAA), 0xA7);
-    // s.add_remote(String::from("Remote B"), u24::new(0xAF), 0xA7);
-    //
-    // let yaml_string = serde_yaml::to_string(&s).unwrap();
-    // println!("Config file:\n{yaml_string:?}");
-    //
-    // s.remove_remote(String::from("Remote A"));
-    // s.remove_remote(String::from("Remote B"));
-    //
-    // assert_eq!(s.remotes.len(), 0);
-    //
-    // s.remotes = serde_yaml::from_str::<HashMap<String, Remote>>(&yaml_string).unwrap();
-    //
-    // println!("{s:?}");
-    // assert_eq!(s.remotes.len(), 2);
-    // assert_eq!(s.address(&String::from("Remote A")), Some(u24::new(0xAA)));
+impl RollingCodeStorage for Storage {
+  type Error = StorageError;
+
+  fn persist(&mut self, remote: &Remote) -> Result<(), Self::Error> {
+    delegate!(self, storage => Ok(storage.persist(remote)?))
+  }
+
+  fn reservation_size(&self) -> u16 {
+    delegate!(self, storage => storage.reservation_size())
+  }
+
+  fn record_transmission(&mut self, frame: &Frame, repetitions: usize, sent: bool) -> Result<(), Self::Error> {
+    delegate!(self, storage => Ok(storage.record_transmission(frame, repetitions, sent)?))
   }
 }
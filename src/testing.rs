@@ -0,0 +1,375 @@
+//! Test doubles for code built on `SendFrame` and `RollingCodeStorage`, usable without GPIO and without sleeping.
+
+use alloc::{collections::BTreeMap, rc::Rc, vec::Vec};
+use core::{
+  cell::{Cell, RefCell},
+  convert::Infallible,
+  fmt,
+  time::Duration,
+};
+
+use embedded_hal::{
+  delay::DelayNs,
+  digital::{ErrorType, OutputPin, PinState},
+};
+use ux::u24;
+
+#[cfg(feature = "async")]
+use crate::AsyncSendFrame;
+use crate::{Frame, Pulse, Remote, RollingCodeStorage, SendFrame, TimingProfile, Waveform};
+
+/// The error returned by test doubles when a failure was injected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InjectedFailure;
+
+impl fmt::Display for InjectedFailure {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Injected failure")
+  }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InjectedFailure {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    None
+  }
+}
+
+/// A transmission recorded by `MemoryStorage::record_transmission`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordedTransmission {
+  pub frame: Frame,
+  pub repetitions: usize,
+  pub sent: bool,
+}
+
+/// A `RollingCodeStorage` keeping rolling codes in memory.
+#[derive(Debug, Clone)]
+pub struct MemoryStorage {
+  rolling_codes: BTreeMap<u24, u16>,
+  reservation_size: u16,
+  persist_failures: usize,
+  record_failures: usize,
+  transmissions: Vec<RecordedTransmission>,
+}
+
+impl Default for MemoryStorage {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl MemoryStorage {
+  pub const fn new() -> Self {
+    Self {
+      rolling_codes: BTreeMap::new(),
+      reservation_size: 1,
+      persist_failures: 0,
+      record_failures: 0,
+      transmissions: Vec::new(),
+    }
+  }
+
+  /// Set the number of rolling codes to reserve with a single write.
+  pub fn reserve(&mut self, reservation_size: u16) -> &mut Self {
+    self.reservation_size = reservation_size;
+    self
+  }
+
+  /// Make the next `count` calls to `persist` fail.
+  pub fn fail_persist(&mut self, count: usize) -> &mut Self {
+    self.persist_failures = count;
+    self
+  }
+
+  /// Make the next `count` calls to `record_transmission` fail.
+  pub fn fail_record(&mut self, count: usize) -> &mut Self {
+    self.record_failures = count;
+    self
+  }
+
+  /// The last persisted rolling code of the remote with the given `address`.
+  pub fn rolling_code(&self, address: u24) -> Option<u16> {
+    self.rolling_codes.get(&address).copied()
+  }
+
+  /// All transmissions recorded so far, including failed ones.
+  pub fn transmissions(&self) -> &[RecordedTransmission] {
+    &self.transmissions
+  }
+}
+
+impl RollingCodeStorage for MemoryStorage {
+  type Error = InjectedFailure;
+
+  fn persist(&mut self, remote: &Remote) -> Result<(), Self::Error> {
+    if take_failure(&mut self.persist_failures) {
+      return Err(InjectedFailure)
+    }
+
+    self.rolling_codes.insert(remote.address(), remote.rolling_code());
+    Ok(())
+  }
+
+  fn reservation_size(&self) -> u16 {
+    self.reservation_size
+  }
+
+  fn record_transmission(&mut self, frame: &Frame, repetitions: usize, sent: bool) -> Result<(), Self::Error> {
+    if take_failure(&mut self.record_failures) {
+      return Err(InjectedFailure)
+    }
+
+    self.transmissions.push(RecordedTransmission { frame: *frame, repetitions, sent });
+    Ok(())
+  }
+}
+
+/// A frame sent with `RecordingSender`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordedFrame {
+  pub frame: Frame,
+  pub repetitions: usize,
+}
+
+/// A `SendFrame` capturing frames instead of sending them.
+#[derive(Debug, Clone, Default)]
+pub struct RecordingSender {
+  timing: TimingProfile,
+  failures: usize,
+  frames: Vec<RecordedFrame>,
+}
+
+impl RecordingSender {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Set the `TimingProfile` reported by `SendFrame::timing`.
+  pub fn timing(&mut self, timing: TimingProfile) -> &mut Self {
+    self.timing = timing;
+    self
+  }
+
+  /// Make the next `count` sends fail. Failed sends are not recorded.
+  pub fn fail(&mut self, count: usize) -> &mut Self {
+    self.failures = count;
+    self
+  }
+
+  /// All frames sent so far.
+  pub fn frames(&self) -> &[RecordedFrame] {
+    &self.frames
+  }
+
+  fn record(&mut self, frame: &Frame, repetitions: usize) -> Result<(), InjectedFailure> {
+    if take_failure(&mut self.failures) {
+      return Err(InjectedFailure)
+    }
+
+    self.frames.push(RecordedFrame { frame: *frame, repetitions });
+    Ok(())
+  }
+}
+
+impl SendFrame for RecordingSender {
+  type Error = InjectedFailure;
+
+  fn timing(&self) -> TimingProfile {
+    self.timing
+  }
+
+  fn send_frame_repeat(&mut self, frame: &Frame, repetitions: usize) -> Result<(), Self::Error> {
+    self.record(frame, repetitions)
+  }
+}
+
+#[cfg(feature = "async")]
+impl AsyncSendFrame for RecordingSender {
+  type Error = InjectedFailure;
+
+  fn timing(&self) -> TimingProfile {
+    self.timing
+  }
+
+  async fn send_frame_repeat(&mut self, frame: &Frame, repetitions: usize) -> Result<(), Self::Error> {
+    self.record(frame, repetitions)
+  }
+}
+
+fn take_failure(failures: &mut usize) -> bool {
+  let fail = *failures > 0;
+  *failures = failures.saturating_sub(1);
+  fail
+}
+
+#[derive(Debug, Default)]
+struct Timeline {
+  now: Cell<u64>,
+  edges: RefCell<Vec<(PinState, u64)>>,
+}
+
+/// A virtual clock shared by a `VirtualPin` and a `VirtualDelay`.
+///
+/// The delay advances the clock without sleeping and the pin records every state change against it,
+/// so the exact waveform of e.g. a `Sender` can be inspected afterwards.
+#[derive(Debug, Clone, Default)]
+pub struct VirtualClock {
+  timeline: Rc<Timeline>,
+}
+
+impl VirtualClock {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Create an `OutputPin` recording against this clock.
+  pub fn pin(&self) -> VirtualPin {
+    VirtualPin { clock: self.clone() }
+  }
+
+  /// Create a `DelayNs` advancing this clock.
+  pub fn delay(&self) -> VirtualDelay {
+    VirtualDelay { clock: self.clone() }
+  }
+
+  /// The virtual time elapsed since this clock was created.
+  pub fn elapsed(&self) -> Duration {
+    Duration::from_nanos(self.timeline.now.get())
+  }
+
+  /// The waveform recorded by the pin, with the last pulse lasting until now.
+  pub fn waveform(&self) -> Waveform {
+    let edges = self.timeline.edges.borrow();
+    let ends = edges.iter().skip(1).map(|&(_, time)| time).chain([self.timeline.now.get()]);
+
+    edges
+      .iter()
+      .zip(ends)
+      .map(|(&(state, start), end)| Pulse { state, duration: ((end - start) / 1000) as u32 })
+      .collect()
+  }
+
+  fn advance(&self, ns: u64) {
+    self.timeline.now.set(self.timeline.now.get() + ns);
+  }
+}
+
+/// An `OutputPin` recording its state changes against a `VirtualClock`.
+#[derive(Debug, Clone)]
+pub struct VirtualPin {
+  clock: VirtualClock,
+}
+
+impl ErrorType for VirtualPin {
+  type Error = Infallible;
+}
+
+impl OutputPin for VirtualPin {
+  fn set_low(&mut self) -> Result<(), Self::Error> {
+    self.set_state(PinState::Low)
+  }
+
+  fn set_high(&mut self) -> Result<(), Self::Error> {
+    self.set_state(PinState::High)
+  }
+
+  fn set_state(&mut self, state: PinState) -> Result<(), Self::Error> {
+    let timeline = &self.clock.timeline;
+    timeline.edges.borrow_mut().push((state, timeline.now.get()));
+    Ok(())
+  }
+}
+
+/// A `DelayNs` advancing a `VirtualClock` instead of sleeping.
+#[derive(Debug, Clone)]
+pub struct VirtualDelay {
+  clock: VirtualClock,
+}
+
+impl DelayNs for VirtualDelay {
+  fn delay_ns(&mut self, ns: u32) {
+    self.clock.advance(u64::from(ns));
+  }
+}
+
+#[cfg(feature = "async")]
+impl embedded_hal_async::delay::DelayNs for VirtualDelay {
+  async fn delay_ns(&mut self, ns: u32) {
+    self.clock.advance(u64::from(ns));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{Command, Error, Sender};
+
+  #[test]
+  fn test_memory_storage() {
+    let mut sender = RecordingSender::new();
+    let mut storage = MemoryStorage::new();
+    storage.reserve(4);
+
+    let mut remote = Remote::new(u24::new(0xFFAA11), 42);
+    remote.send_repeat(&mut sender, &mut storage, Command::Up, 2).unwrap();
+    remote.send_repeat(&mut sender, &mut storage, Command::Down, 0).unwrap();
+
+    assert_eq!(storage.rolling_code(remote.address()), Some(46));
+    assert_eq!(sender.frames().len(), 2);
+    assert_eq!(sender.frames()[0].frame.command(), Command::Up);
+    assert_eq!(sender.frames()[0].repetitions, 2);
+    assert_eq!(sender.frames()[1].frame.rolling_code(), 43);
+
+    let transmissions = storage.transmissions();
+    assert_eq!(transmissions.len(), 2);
+    assert!(transmissions.iter().all(|transmission| transmission.sent));
+  }
+
+  #[test]
+  fn test_injected_failures() {
+    let mut sender = RecordingSender::new();
+    let mut storage = MemoryStorage::new();
+    let mut remote = Remote::new(u24::new(0xFFAA11), 42);
+
+    storage.fail_persist(1);
+    assert!(matches!(
+      remote.send_repeat(&mut sender, &mut storage, Command::Up, 0),
+      Err(Error::StorageError(InjectedFailure))
+    ));
+    assert!(sender.frames().is_empty());
+
+    sender.fail(1);
+    assert!(matches!(
+      remote.send_repeat(&mut sender, &mut storage, Command::Up, 0),
+      Err(Error::TransmitError(InjectedFailure))
+    ));
+    assert_eq!(storage.transmissions().len(), 1);
+    assert!(!storage.transmissions()[0].sent);
+
+    // Failing to record a transmission does not fail sending.
+    storage.fail_record(1);
+    remote.send_repeat(&mut sender, &mut storage, Command::Up, 0).unwrap();
+    assert_eq!(sender.frames().len(), 1);
+    assert_eq!(storage.transmissions().len(), 1);
+  }
+
+  #[test]
+  fn test_virtual_clock() {
+    let frame = Frame::builder()
+      .key(0xA7)
+      .command(Command::Up)
+      .rolling_code(42)
+      .remote_address(u24::new(0xFFAA11))
+      .build()
+      .expect("Failed to build frame");
+
+    let clock = VirtualClock::new();
+    let mut sender = Sender::new(clock.pin(), clock.delay());
+    SendFrame::send_frame_repeat(&mut sender, &frame, 1).unwrap();
+
+    let waveform = Waveform::for_frame(&frame, 1);
+    assert_eq!(clock.waveform(), waveform);
+    assert_eq!(clock.elapsed(), waveform.airtime());
+  }
+}
@@ -4,21 +4,373 @@ use std::{
   cmp::Ordering,
   collections::HashMap,
   error::Error,
-  sync::{Arc, Mutex, RwLock, Weak},
+  sync::{Arc, Mutex, OnceLock, RwLock, Weak},
   thread,
+  time::{Duration, Instant},
 };
 
 use serde_json::json;
 use uuid::Uuid;
 use webthing::{server::ActionGenerator, Action, BaseAction, BaseProperty, BaseThing, Thing};
 
-use crate::Storage;
-use somfy::{Command, Remote, SendFrame};
+use crate::{StateFile, Storage};
+use somfy::{Calibration, CalibrationStep, Command, Direction, PositionEstimator, Remote, RemoteConfig, SendFrame};
 
-pub struct Generator<S: SendFrame> {
+/// Position assumed for blinds at startup if their state is unknown.
+pub const INITIAL_POSITION: u8 = 50;
+
+/// Number of command repetitions for remotes without configured repetitions.
+pub const DEFAULT_REPETITIONS: usize = 2;
+
+/// Extra time to wait for a blind to reach an end stop when re-homing, since travel times are only measured roughly.
+pub const REHOME_MARGIN: Duration = Duration::from_secs(5);
+
+/// Time elapsed since the server started, used as timestamp for position estimation.
+fn now() -> Duration {
+  static START: OnceLock<Instant> = OnceLock::new();
+  START.get_or_init(Instant::now).elapsed()
+}
+
+/// The clock used to time blind movements.
+pub trait Clock {
+  /// Timestamp for position estimation.
+  fn now(&self) -> Duration;
+
+  /// Block for the given `duration`.
+  fn sleep(&self, duration: Duration);
+}
+
+/// The system clock, timestamping relative to when the server started.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+  fn now(&self) -> Duration {
+    now()
+  }
+
+  fn sleep(&self, duration: Duration) {
+    thread::sleep(duration)
+  }
+}
+
+/// Pick up rolling codes used by other processes, e.g. the command line, before sending.
+fn refresh(storage: &mut Storage, remote: &mut Remote) {
+  if let Err(err) = storage.refresh(remote) {
+    log::warn!("Failed to re-read config for remote {}: {err}", remote.address());
+  }
+}
+
+/// Everything needed to control a single blind.
+pub struct Blind<S> {
+  pub name: String,
+  pub venetian: bool,
+  pub repetitions: usize,
   pub sender: Arc<Mutex<S>>,
-  pub storage: Arc<RwLock<Storage>>,
-  pub remotes: HashMap<String, Arc<RwLock<Remote>>>,
+  pub storage: Arc<Mutex<Storage>>,
+  pub state: Arc<Mutex<StateFile>>,
+  pub remote: Arc<RwLock<Remote>>,
+  pub estimator: Arc<Mutex<Option<PositionEstimator>>>,
+  pub calibration: Arc<Mutex<Option<Calibration>>>,
+}
+
+impl<S> Clone for Blind<S> {
+  fn clone(&self) -> Self {
+    Self {
+      name: self.name.clone(),
+      venetian: self.venetian,
+      repetitions: self.repetitions,
+      sender: self.sender.clone(),
+      storage: self.storage.clone(),
+      state: self.state.clone(),
+      remote: self.remote.clone(),
+      estimator: self.estimator.clone(),
+      calibration: self.calibration.clone(),
+    }
+  }
+}
+
+impl<S, E> Blind<S>
+where
+  S: SendFrame<Error = E>,
+  E: Error,
+{
+  fn send(&self, command: Command, hold: Option<Duration>) -> bool {
+    // Short presses only tilt the slats of venetian blinds.
+    let hold = match (hold, command) {
+      (None, Command::Up | Command::Down) if self.venetian => Some(Direction::TRAVEL_HOLD),
+      (hold, _) => hold,
+    };
+
+    let mut sender = self.sender.lock().unwrap();
+    let mut storage = self.storage.lock().unwrap();
+    let mut remote = self.remote.write().unwrap();
+    refresh(&mut storage, &mut remote);
+
+    log::info!("Sending command {command:?} with remote {}.", remote.address());
+    let result = match hold {
+      Some(hold) => remote.send_hold(&mut *sender, &mut *storage, command, hold),
+      None => remote.send_repeat(&mut *sender, &mut *storage, command, self.repetitions),
+    };
+
+    match result {
+      Ok(()) => true,
+      Err(err) => {
+        log::error!("Failed to send command {command:?}: {err}");
+        false
+      },
+    }
+  }
+
+  fn record(&self, position: Option<u8>, moving_to: Option<u8>, command: Command) {
+    if let Err(err) = self.state.lock().unwrap().record(&self.name, position, moving_to, command) {
+      log::error!("Failed to write state of {}: {err}", self.name);
+    }
+  }
+
+  fn record_estimate(&self, estimator: &PositionEstimator, now: Duration, command: Command) {
+    self.record(Some(estimator.position(now)), estimator.target(now), command)
+  }
+
+  /// Move the blind to the `target` position, returning the new position if a command was sent.
+  ///
+  /// If the travel times are known, this blocks until the blind has reached the target position.
+  pub fn move_to(&self, target: u8, current_position: u8, hold: Option<Duration>, clock: &impl Clock) -> Option<u8> {
+    let my_position = self.storage.lock().unwrap().remote_config(&self.name).and_then(|config| config.my_position);
+
+    if self.estimator.lock().unwrap().is_none() {
+      let command = match target {
+        0 => Command::Down,
+        100 => Command::Up,
+        p if Some(p) == my_position => Command::My,
+        p => match p.cmp(&current_position) {
+          Ordering::Less => Command::Down,
+          Ordering::Equal => return None,
+          Ordering::Greater => Command::Up,
+        },
+      };
+
+      if !self.send(command, hold) {
+        return None
+      }
+
+      self.record(Some(target), None, command);
+      return Some(target)
+    }
+
+    let motion = {
+      let mut estimator = self.estimator.lock().unwrap();
+      let estimator = estimator.as_mut()?;
+      let started = clock.now();
+
+      // Sending My to a stopped blind moves it to its favourite position.
+      let command = match estimator.move_to(target, started)? {
+        _ if my_position == Some(target) && !estimator.is_moving(started) => Command::My,
+        (direction, _) => direction.command(),
+      };
+
+      if !self.send(command, hold) {
+        return None
+      }
+
+      if command == Command::My {
+        estimator.move_towards(target, started);
+      } else {
+        estimator.command(command, started);
+      }
+      self.record_estimate(estimator, started, command);
+
+      estimator.motion().zip(estimator.move_to(target, started).map(|(_, duration)| duration))
+    };
+
+    if let Some((motion, duration)) = motion {
+      clock.sleep(duration.saturating_sub(clock.now().saturating_sub(motion.since)));
+
+      let mut estimator = self.estimator.lock().unwrap();
+      let estimator = estimator.as_mut()?;
+
+      // Only stop the blind if it does not stop by itself and no other command was sent in the meantime.
+      if estimator.motion() == Some(motion) && estimator.is_moving(clock.now()) {
+        let stopped = clock.now();
+        if self.send(Command::My, None) {
+          estimator.stop(stopped);
+          self.record_estimate(estimator, stopped, Command::My);
+        }
+      }
+    }
+
+    self.estimator.lock().unwrap().as_ref().map(|estimator| estimator.position(clock.now()))
+  }
+
+  /// Move the blind to the nearest end stop and back, resetting its estimated position at the end stop.
+  ///
+  /// Returns the new position if the blind was re-homed.
+  pub fn rehome(&self, clock: &impl Clock) -> Option<u8> {
+    let (position, end_stop, command, motion, travel_time) = {
+      let mut estimator = self.estimator.lock().unwrap();
+      let estimator = estimator.as_mut()?;
+      let started = clock.now();
+
+      if estimator.is_moving(started) {
+        return None
+      }
+
+      let position = estimator.position(started);
+      let (end_stop, command, travel_time) = if position < 50 {
+        (0, Command::Down, estimator.close_time())
+      } else {
+        (100, Command::Up, estimator.open_time())
+      };
+
+      log::info!("Re-homing {}.", self.name);
+      if !self.send(command, None) {
+        return None
+      }
+
+      estimator.command(command, started);
+      self.record_estimate(estimator, started, command);
+
+      (position, end_stop, command, estimator.motion(), travel_time)
+    };
+
+    // The estimated position may be off by any amount, so wait for a full travel to the end stop.
+    clock.sleep(travel_time + REHOME_MARGIN);
+
+    {
+      let mut estimator = self.estimator.lock().unwrap();
+      let estimator = estimator.as_mut()?;
+
+      // Another command was sent in the meantime, so the blind is not necessarily at the end stop.
+      if estimator.motion() != motion {
+        return None
+      }
+
+      *estimator = PositionEstimator::new(estimator.open_time(), estimator.close_time(), end_stop)
+        .with_my_position(estimator.my_position());
+      self.record(Some(end_stop), None, command);
+    }
+
+    if position == end_stop {
+      return Some(end_stop)
+    }
+
+    self.move_to(position, end_stop, None, clock)
+  }
+
+  fn tilt(&self, direction: Direction, steps: usize) -> bool {
+    let mut sender = self.sender.lock().unwrap();
+    let mut storage = self.storage.lock().unwrap();
+    let mut remote = self.remote.write().unwrap();
+    refresh(&mut storage, &mut remote);
+
+    log::info!("Tilting {direction:?} by {steps} steps with remote {}.", remote.address());
+    match remote.tilt_step(&mut *sender, &mut *storage, direction, steps) {
+      Ok(()) => true,
+      Err(err) => {
+        log::error!("Failed to tilt {direction:?}: {err}");
+        false
+      },
+    }
+  }
+
+  fn store_my_position(&self, position: u8) {
+    let result = {
+      let mut sender = self.sender.lock().unwrap();
+      let mut storage = self.storage.lock().unwrap();
+      let mut remote = self.remote.write().unwrap();
+      refresh(&mut storage, &mut remote);
+
+      log::info!("Storing favourite position {position} % with remote {}.", remote.address());
+      match remote.store_my_position(&mut *sender, &mut *storage) {
+        Ok(()) => storage.set_my_position(&self.name, Some(position)).map_err(|err| err.to_string()),
+        Err(err) => Err(err.to_string()),
+      }
+    };
+
+    match result {
+      Ok(()) => {
+        if let Some(estimator) = self.estimator.lock().unwrap().as_mut() {
+          estimator.set_my_position(Some(position));
+        }
+      },
+      Err(err) => log::error!("Failed to store favourite position: {err}"),
+    }
+  }
+
+  /// Advance the calibration, returning the new position once it is done.
+  fn calibrate(&self, step: &str) -> Option<u8> {
+    let mut calibration = self.calibration.lock().unwrap();
+
+    let step = match step {
+      "start" => {
+        let (new_calibration, direction) = Calibration::start();
+        *calibration = Some(new_calibration);
+        CalibrationStep::Move(direction)
+      },
+      "end_stop" => match calibration.as_mut() {
+        Some(calibration) => calibration.end_stop(now()),
+        None => {
+          log::error!("No calibration of {} in progress.", self.name);
+          return None
+        },
+      },
+      step => {
+        log::error!("Unknown calibration step: {step}");
+        return None
+      },
+    };
+
+    match step {
+      CalibrationStep::Move(direction) => {
+        if !self.send(direction.command(), None) {
+          *calibration = None;
+        }
+        None
+      },
+      CalibrationStep::Done { open_time, close_time } => {
+        *calibration = None;
+
+        let estimator = {
+          let mut storage = self.storage.lock().unwrap();
+          if let Err(err) = storage.set_travel_times(&self.name, open_time, close_time) {
+            log::error!("Failed to store travel times of {}: {err}", self.name);
+            return None
+          }
+
+          storage.remote_config(&self.name).and_then(|config| config.estimator(0))
+        };
+
+        log::info!("Calibrated {}: open time {open_time:?}, close time {close_time:?}", self.name);
+        *self.estimator.lock().unwrap() = estimator;
+        self.record(Some(0), None, Command::Down);
+        Some(0)
+      },
+    }
+  }
+}
+
+/// A `Thing` shared between the server and its actions.
+pub type SharedThing = Arc<RwLock<Box<dyn Thing>>>;
+
+/// Periodically move all blinds with known travel times to the nearest end stop and back,
+/// resetting the error accumulated by position estimation.
+pub fn rehome<S, E>(blinds: Vec<(Blind<S>, SharedThing)>, interval: Duration)
+where
+  S: SendFrame<Error = E> + Send + 'static,
+  E: Error + Send + Sync + 'static,
+{
+  thread::spawn(move || loop {
+    thread::sleep(interval);
+
+    for (blind, thing) in &blinds {
+      if let Some(new_position) = blind.rehome(&SystemClock) {
+        thing.write().unwrap().set_property("position".to_owned(), json!(new_position)).unwrap();
+      }
+    }
+  });
+}
+
+pub struct Generator<S> {
+  pub blinds: HashMap<String, Blind<S>>,
 }
 
 impl<S, E> ActionGenerator for Generator<S>
@@ -33,43 +385,37 @@ where
     input: Option<&serde_json::Value>,
   ) -> Option<Box<dyn Action>> {
     let input = input.and_then(|v| v.as_object()).cloned();
-    let thing_id = thing.upgrade()?.write().unwrap().get_id();
-    let remote = self.remotes.get(&thing_id).cloned()?;
+    let thing_id = thing.upgrade()?.read().unwrap().get_id();
+    let blind = self.blinds.get(&thing_id).cloned()?;
 
     log::info!("Generating {name} action for {thing_id}: {input:?}");
 
     match name.as_ref() {
-      "move" => Some(Box::new(MoveAction::new(input, thing, self.sender.clone(), self.storage.clone(), remote))),
+      "move" | "tilt" | "store_my_position" | "calibrate" => {
+        Some(Box::new(BlindAction::new(name, input, thing, blind)))
+      },
       _ => None,
     }
   }
 }
 
-pub struct MoveAction<S> {
+pub struct BlindAction<S> {
   action: BaseAction,
-  sender: Arc<Mutex<S>>,
-  storage: Arc<RwLock<Storage>>,
-  remote: Arc<RwLock<Remote>>,
+  blind: Blind<S>,
 }
 
-impl<S> MoveAction<S> {
+impl<S> BlindAction<S> {
   fn new(
+    name: String,
     input: Option<serde_json::Map<String, serde_json::Value>>,
     thing: Weak<RwLock<Box<dyn Thing>>>,
-    sender: Arc<Mutex<S>>,
-    storage: Arc<RwLock<Storage>>,
-    remote: Arc<RwLock<Remote>>,
+    blind: Blind<S>,
   ) -> Self {
-    Self {
-      action: BaseAction::new(Uuid::new_v4().to_string(), "move".to_owned(), input, thing),
-      sender: sender,
-      storage: storage,
-      remote,
-    }
+    Self { action: BaseAction::new(Uuid::new_v4().to_string(), name, input, thing), blind }
   }
 }
 
-impl<S, E> Action for MoveAction<S>
+impl<S, E> Action for BlindAction<S>
 where
   S: SendFrame<Error = E> + Send + 'static,
   E: Error + Send + Sync + 'static,
@@ -120,45 +466,38 @@ where
 
   fn perform_action(&mut self) {
     let thing = if let Some(thing) = self.get_thing() { thing } else { return };
-    let input = self.get_input().unwrap().clone();
+    let input = self.get_input().unwrap_or_default();
     let name = self.get_name();
     let id = self.get_id();
 
-    let sender = self.sender.clone();
-    let storage = self.storage.clone();
-    let remote = self.remote.clone();
+    let blind = self.blind.clone();
 
     thread::spawn(move || {
-      let thing = thing.clone();
-      let mut thing = thing.write().unwrap();
+      let current_position = thing.read().unwrap().get_property("position").unwrap().as_u64().unwrap().min(100) as u8;
 
-      let current_position = thing.find_property(&"position".to_owned()).unwrap().get_value().as_u64().unwrap();
-      let target_position_value = input.get("position").unwrap();
-      let target_position = target_position_value.as_u64().unwrap();
-
-      let command = match target_position {
-        0 => Command::Down,
-        45..=55 => Command::My,
-        100 => Command::Up,
-        p => match p.cmp(&current_position) {
-          Ordering::Less => Command::Down,
-          Ordering::Equal => return,
-          Ordering::Greater => Command::Up,
+      let position = match name.as_str() {
+        "move" => perform_move(&blind, &input, current_position),
+        "tilt" => {
+          perform_tilt(&blind, &thing, &input);
+          None
         },
+        "store_my_position" => {
+          let position = input.get("position").and_then(|position| position.as_u64());
+          blind.store_my_position(position.map_or(current_position, |position| position.min(100) as u8));
+          None
+        },
+        "calibrate" => blind.calibrate(input.get("step").and_then(|step| step.as_str()).unwrap_or_default()),
+        _ => None,
       };
 
-      let mut sender = sender.lock().unwrap();
-      let mut storage = storage.write().unwrap();
-      let mut remote = remote.write().unwrap();
+      let mut thing = thing.write().unwrap();
 
-      log::info!("Sending command {command:?} with remote {}.", remote.address());
-      match remote.send_repeat(&mut *sender, &mut *storage, command, 2) {
-        Ok(()) => {
-          thing.set_property("position".to_owned(), target_position_value.clone()).unwrap();
-        },
-        Err(err) => {
-          log::error!("Failed to send command {command:?}: {err}");
-        },
+      if let Some(position) = position {
+        thing.set_property("position".to_owned(), json!(position)).unwrap();
+
+        if blind.venetian {
+          thing.set_property("tilt".to_owned(), json!(0)).unwrap();
+        }
       }
 
       thing.finish_action(name, id);
@@ -174,7 +513,60 @@ where
   }
 }
 
-pub fn make_remote(name: &str, remote: &Remote) -> BaseThing {
+fn perform_move<S, E>(
+  blind: &Blind<S>,
+  input: &serde_json::Map<String, serde_json::Value>,
+  current_position: u8,
+) -> Option<u8>
+where
+  S: SendFrame<Error = E>,
+  E: Error,
+{
+  let target_position = input.get("position").and_then(|position| position.as_u64())?.min(100) as u8;
+
+  let hold = match input.get("hold").and_then(|hold| hold.as_str()).map(humantime::parse_duration) {
+    Some(Ok(hold)) => Some(hold),
+    Some(Err(err)) => {
+      log::error!("Invalid hold duration: {err}");
+      return None
+    },
+    None => None,
+  };
+
+  blind.move_to(target_position, current_position, hold, &SystemClock)
+}
+
+fn perform_tilt<S, E>(
+  blind: &Blind<S>,
+  thing: &RwLock<Box<dyn Thing>>,
+  input: &serde_json::Map<String, serde_json::Value>,
+) where
+  S: SendFrame<Error = E>,
+  E: Error,
+{
+  let direction = match input.get("direction").and_then(|direction| direction.as_str()).map(str::parse::<Direction>) {
+    Some(Ok(direction)) => direction,
+    _ => {
+      log::error!("Invalid tilt direction.");
+      return
+    },
+  };
+  let steps = input.get("steps").and_then(|steps| steps.as_u64()).unwrap_or(1);
+
+  if blind.tilt(direction, steps as usize) {
+    let mut thing = thing.write().unwrap();
+    let tilt = thing.get_property("tilt").unwrap().as_i64().unwrap();
+    let tilt = match direction {
+      Direction::Up => tilt + steps as i64,
+      Direction::Down => tilt - steps as i64,
+    };
+    thing.set_property("tilt".to_owned(), json!(tilt)).unwrap();
+  }
+}
+
+pub fn make_remote(name: &str, config: &RemoteConfig, position: u8) -> BaseThing {
+  let remote = &config.remote;
+
   let mut thing = BaseThing::new(
     format!("urn:dev:ops:somfy-rts-{}", remote.address()),
     name.to_owned(),
@@ -192,7 +584,12 @@ pub fn make_remote(name: &str, remote: &Remote) -> BaseThing {
     "unit": "percent"
   });
   let position_description = position_description.as_object().unwrap().clone();
-  thing.add_property(Box::new(BaseProperty::new("position".to_owned(), json!(50), None, Some(position_description))));
+  thing.add_property(Box::new(BaseProperty::new(
+    "position".to_owned(),
+    json!(position),
+    None,
+    Some(position_description),
+  )));
 
   let move_metadata = json!({
     "title": "Move",
@@ -208,6 +605,10 @@ pub fn make_remote(name: &str, remote: &Remote) -> BaseThing {
           "minimum": 0,
           "maximum": 100,
           "unit": "percent"
+        },
+        "hold": {
+          "type": "string",
+          "description": "Duration to hold the button, e.g. “2s”"
         }
       }
     }
@@ -215,5 +616,134 @@ pub fn make_remote(name: &str, remote: &Remote) -> BaseThing {
   let move_metadata = move_metadata.as_object().unwrap().clone();
   thing.add_available_action("move".to_owned(), move_metadata);
 
+  let store_my_position_metadata = json!({
+    "title": "Store favourite position",
+    "description": "Store the current position of the blind as its favourite position",
+    "input": {
+      "type": "object",
+      "properties": {
+        "position": {
+          "type": "integer",
+          "minimum": 0,
+          "maximum": 100,
+          "unit": "percent"
+        }
+      }
+    }
+  });
+  let store_my_position_metadata = store_my_position_metadata.as_object().unwrap().clone();
+  thing.add_available_action("store_my_position".to_owned(), store_my_position_metadata);
+
+  let calibrate_metadata = json!({
+    "title": "Calibrate",
+    "description": "Measure the travel times of the blind: start closes the blind, then signal each end stop once it is reached",
+    "input": {
+      "type": "object",
+      "required": [
+        "step"
+      ],
+      "properties": {
+        "step": {
+          "type": "string",
+          "enum": ["start", "end_stop"]
+        }
+      }
+    }
+  });
+  let calibrate_metadata = calibrate_metadata.as_object().unwrap().clone();
+  thing.add_available_action("calibrate".to_owned(), calibrate_metadata);
+
+  if config.is_venetian() {
+    let tilt_description = json!({
+      "title": "Tilt",
+      "type": "integer",
+      "description": "The number of steps the slats were tilted up since the blind last moved"
+    });
+    let tilt_description = tilt_description.as_object().unwrap().clone();
+    thing.add_property(Box::new(BaseProperty::new("tilt".to_owned(), json!(0), None, Some(tilt_description))));
+
+    let tilt_metadata = json!({
+      "title": "Tilt",
+      "description": "Tilt the slats of the blind",
+      "input": {
+        "type": "object",
+        "required": [
+          "direction"
+        ],
+        "properties": {
+          "direction": {
+            "type": "string",
+            "enum": ["up", "down"]
+          },
+          "steps": {
+            "type": "integer",
+            "minimum": 1
+          }
+        }
+      }
+    });
+    let tilt_metadata = tilt_metadata.as_object().unwrap().clone();
+    thing.add_available_action("tilt".to_owned(), tilt_metadata);
+  }
+
   thing
 }
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+  use somfy::testing::{RecordingSender, VirtualClock};
+  use ux::u24;
+
+  use super::*;
+
+  impl Clock for VirtualClock {
+    fn now(&self) -> Duration {
+      self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+      use embedded_hal::delay::DelayNs;
+
+      self.delay().delay_us(u32::try_from(duration.as_micros()).unwrap())
+    }
+  }
+
+  #[test]
+  fn test_rehome() {
+    let dir = tempfile::tempdir().unwrap();
+    let remote = Remote::new(u24::new(0xFFAA11), 42);
+    let mut storage = Storage::open(None, &dir.path().join("remotes.yaml"), None).unwrap();
+    storage.add_remote("blind".to_owned(), remote.clone()).unwrap();
+
+    let open_time = Duration::from_secs(20);
+    let close_time = Duration::from_secs(18);
+
+    // The blind is estimated at 30 % but may actually be fully open.
+    let blind = Blind {
+      name: "blind".to_owned(),
+      venetian: false,
+      repetitions: DEFAULT_REPETITIONS,
+      sender: Arc::new(Mutex::new(RecordingSender::new())),
+      storage: Arc::new(Mutex::new(storage)),
+      state: Arc::new(Mutex::new(StateFile::open(dir.path().join("state.yaml")).unwrap())),
+      remote: Arc::new(RwLock::new(remote)),
+      estimator: Arc::new(Mutex::new(Some(PositionEstimator::new(open_time, close_time, 30)))),
+      calibration: Arc::new(Mutex::new(None)),
+    };
+
+    let clock = VirtualClock::new();
+    assert_eq!(blind.rehome(&clock), Some(30));
+
+    let commands: Vec<_> =
+      blind.sender.lock().unwrap().frames().iter().map(|recorded| recorded.frame.command()).collect();
+    assert_eq!(commands, [Command::Down, Command::Up, Command::My]);
+
+    // Reversing only after the full close time gives the blind enough time to reach the end stop.
+    assert_eq!(clock.elapsed(), close_time + REHOME_MARGIN + open_time * 30 / 100);
+
+    let estimator = blind.estimator.lock().unwrap();
+    let estimator = estimator.as_ref().unwrap();
+    assert!(!estimator.is_moving(clock.elapsed()));
+    assert_eq!(estimator.position(clock.elapsed()), 30);
+  }
+}
@@ -0,0 +1,143 @@
+use core::{fmt, str::FromStr, time::Duration};
+
+use ux::u24;
+
+use crate::{waveform::pulses, Command, Frame};
+
+#[derive(Debug)]
+pub struct UnknownTimingProfile;
+
+impl fmt::Display for UnknownTimingProfile {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Unknown timing profile")
+  }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for UnknownTimingProfile {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    None
+  }
+}
+
+/// Timing used for sending and receiving frames. All durations are in µs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimingProfile {
+  /// Width of a Manchester-encoded symbol, i.e. a single bit.
+  pub symbol_width: u32,
+  pub wake_up_high: u32,
+  pub wake_up_low: u32,
+  /// Number of hardware sync pulses before the first frame.
+  pub hardware_sync_once: usize,
+  /// Number of hardware sync pulses before each repeated frame.
+  pub hardware_sync_repeat: usize,
+  pub software_sync: u32,
+  pub inter_frame_gap: u32,
+  /// Added to every high pulse and subtracted from every low pulse when sending,
+  /// e.g. to compensate for the time a transmitter needs to turn on.
+  pub correction: i32,
+}
+
+impl TimingProfile {
+  /// Timing of original Somfy RTS remotes.
+  pub const SOMFY: Self = Self {
+    symbol_width: 1280,
+    wake_up_high: 9_415,
+    wake_up_low: 89_565,
+    hardware_sync_once: 2,
+    hardware_sync_repeat: 7,
+    software_sync: 4_550,
+    inter_frame_gap: 30_415,
+    correction: 0,
+  };
+
+  /// Set the timing `correction` in µs.
+  pub const fn with_correction(self, correction: i32) -> Self {
+    Self { correction, ..self }
+  }
+
+  /// The number of repetitions needed for a frame to be sent for at least the given `hold` duration,
+  /// like a button held down on a remote.
+  pub fn repetitions_for(&self, hold: Duration) -> usize {
+    // Only the timing correction depends on the frame contents, so any frame can be used here.
+    let frame = Frame::builder()
+      .key(0)
+      .command(Command::My)
+      .rolling_code(0)
+      .remote_address(u24::new(0))
+      .build()
+      .expect("Failed to build frame");
+
+    let airtime = |repetitions| pulses(&frame, repetitions, self).map(|pulse| u64::from(pulse.duration)).sum::<u64>();
+    let first = airtime(0);
+    let repeat = airtime(1).saturating_sub(first).max(1);
+
+    let remaining = u64::try_from(hold.as_micros()).unwrap_or(u64::MAX).saturating_sub(first);
+    usize::try_from(remaining.div_ceil(repeat)).unwrap_or(usize::MAX)
+  }
+
+  pub(crate) fn corrected_high(&self, duration: u32) -> u32 {
+    duration.saturating_add_signed(self.correction)
+  }
+
+  pub(crate) fn corrected_low(&self, duration: u32) -> u32 {
+    duration.saturating_add_signed(self.correction.saturating_neg())
+  }
+}
+
+impl Default for TimingProfile {
+  fn default() -> Self {
+    Self::SOMFY
+  }
+}
+
+impl FromStr for TimingProfile {
+  type Err = UnknownTimingProfile;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let profiles = [("somfy", TimingProfile::SOMFY)];
+
+    for (string, profile) in profiles {
+      if s.eq_ignore_ascii_case(string) {
+        return Ok(profile)
+      }
+    }
+
+    Err(UnknownTimingProfile)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Waveform;
+
+  #[test]
+  fn test_repetitions_for() {
+    let frame = Frame::builder()
+      .key(0xA7)
+      .command(Command::Prog)
+      .rolling_code(42)
+      .remote_address(u24::new(0xFFAA11))
+      .build()
+      .expect("Failed to build frame");
+
+    let timings = [
+      TimingProfile::SOMFY,
+      TimingProfile { symbol_width: 1000, ..TimingProfile::SOMFY },
+      TimingProfile { wake_up_high: 12_000, ..TimingProfile::SOMFY },
+      TimingProfile::SOMFY.with_correction(-150),
+    ];
+
+    for timing in timings {
+      assert_eq!(timing.repetitions_for(Duration::ZERO), 0);
+
+      for hold in [Duration::from_millis(500), Duration::from_secs(2), Duration::from_secs(5)] {
+        let repetitions = timing.repetitions_for(hold);
+        assert!(Waveform::for_frame_with_timing(&frame, repetitions, &timing).airtime() >= hold);
+        assert!(Waveform::for_frame_with_timing(&frame, repetitions - 1, &timing).airtime() < hold);
+      }
+    }
+  }
+}
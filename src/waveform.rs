@@ -0,0 +1,211 @@
+use alloc::vec::Vec;
+use core::{iter, time::Duration};
+
+use embedded_hal::digital::PinState::{self, *};
+
+use crate::{Frame, TimingProfile};
+
+/// A pin state held for a duration in µs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pulse {
+  pub state: PinState,
+  pub duration: u32,
+}
+
+impl From<Pulse> for (PinState, u32) {
+  fn from(pulse: Pulse) -> Self {
+    (pulse.state, pulse.duration)
+  }
+}
+
+/// The exact sequence of pulses sent for a frame, including the wake-up pulse and inter-frame gaps.
+///
+/// Consecutive pulses never share the same state.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Waveform {
+  pulses: Vec<Pulse>,
+}
+
+impl Waveform {
+  pub const fn new() -> Self {
+    Self { pulses: Vec::new() }
+  }
+
+  /// Create an empty `Waveform` with enough capacity to record a frame with the given number of `repetitions`.
+  pub fn with_capacity_for(repetitions: usize) -> Self {
+    Self { pulses: Vec::with_capacity(capacity(repetitions, &TimingProfile::default())) }
+  }
+
+  /// Create the `Waveform` for sending a `Frame` with a given number of `repetitions`.
+  pub fn for_frame(frame: &Frame, repetitions: usize) -> Self {
+    Self::for_frame_with_timing(frame, repetitions, &TimingProfile::default())
+  }
+
+  /// Create the `Waveform` for sending a `Frame` with a given number of `repetitions` using the given `timing`.
+  pub fn for_frame_with_timing(frame: &Frame, repetitions: usize, timing: &TimingProfile) -> Self {
+    let mut waveform = Self::new();
+    waveform.record(frame, repetitions, timing);
+    waveform
+  }
+
+  /// Replace the contents of this `Waveform` with the pulses for the given `Frame`, reusing the existing allocation.
+  pub fn record(&mut self, frame: &Frame, repetitions: usize, timing: &TimingProfile) {
+    self.pulses.clear();
+    self.pulses.reserve(capacity(repetitions, timing));
+    self.pulses.extend(pulses(frame, repetitions, timing));
+  }
+
+  pub fn pulses(&self) -> &[Pulse] {
+    &self.pulses
+  }
+
+  pub fn into_pulses(self) -> Vec<Pulse> {
+    self.pulses
+  }
+
+  /// The total time needed to send this `Waveform`.
+  pub fn airtime(&self) -> Duration {
+    Duration::from_micros(self.pulses.iter().map(|pulse| u64::from(pulse.duration)).sum())
+  }
+}
+
+/// Collect pulses into a `Waveform`, merging consecutive pulses with the same state.
+impl FromIterator<Pulse> for Waveform {
+  fn from_iter<I: IntoIterator<Item = Pulse>>(pulses: I) -> Self {
+    Self { pulses: Merge { pulses: pulses.into_iter().peekable() }.collect() }
+  }
+}
+
+fn capacity(repetitions: usize, timing: &TimingProfile) -> usize {
+  // Hardware sync, software sync, 56 Manchester-encoded bits and inter-frame gap.
+  let frame_pulses = |sync_count| 2 * sync_count + 2 + 2 * 56 + 1;
+
+  2 + frame_pulses(timing.hardware_sync_once) + repetitions * frame_pulses(timing.hardware_sync_repeat)
+}
+
+/// Generate the pulses for sending a `Frame` with a given number of `repetitions` without allocating.
+///
+/// Consecutive pulses with the same state are merged and the timing correction is applied to the merged pulses.
+pub(crate) fn pulses(frame: &Frame, repetitions: usize, timing: &TimingProfile) -> impl Iterator<Item = Pulse> {
+  let timing = *timing;
+  let bytes = *frame.as_bytes();
+
+  let pulse = |state, duration| Pulse { state, duration };
+  let sync_width = 2 * timing.symbol_width;
+  let half_symbol = timing.symbol_width / 2;
+
+  let frame_pulses = move |sync_count| {
+    let hardware_sync = iter::repeat_n([pulse(High, sync_width), pulse(Low, sync_width)], sync_count).flatten();
+    let software_sync = [pulse(High, timing.software_sync), pulse(Low, half_symbol)];
+
+    // Send each byte starting with the most significant bit, using Manchester encoding.
+    let bits =
+      bytes.into_iter().flat_map(|byte| (0..=7).rev().map(move |bit| byte & (1 << bit) != 0)).flat_map(move |bit| {
+        let (from, to) = if bit { (Low, High) } else { (High, Low) };
+        [pulse(from, half_symbol), pulse(to, half_symbol)]
+      });
+
+    hardware_sync.chain(software_sync).chain(bits).chain([pulse(Low, timing.inter_frame_gap)])
+  };
+
+  let wake_up = [pulse(High, timing.wake_up_high), pulse(Low, timing.wake_up_low)];
+  let frames = frame_pulses(timing.hardware_sync_once)
+    .chain(iter::repeat_n(timing.hardware_sync_repeat, repetitions).flat_map(frame_pulses));
+
+  Merge { pulses: wake_up.into_iter().chain(frames).peekable() }.map(move |pulse| {
+    let duration = match pulse.state {
+      High => timing.corrected_high(pulse.duration),
+      Low => timing.corrected_low(pulse.duration),
+    };
+
+    Pulse { duration, ..pulse }
+  })
+}
+
+struct Merge<I: Iterator<Item = Pulse>> {
+  pulses: iter::Peekable<I>,
+}
+
+impl<I> Iterator for Merge<I>
+where
+  I: Iterator<Item = Pulse>,
+{
+  type Item = Pulse;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let mut pulse = self.pulses.next()?;
+
+    while let Some(next) = self.pulses.next_if(|next| next.state == pulse.state) {
+      pulse.duration += next.duration;
+    }
+
+    Some(pulse)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use ux::u24;
+
+  use super::*;
+  use crate::Command;
+
+  const TIMING: TimingProfile = TimingProfile::SOMFY;
+
+  fn frame() -> Frame {
+    Frame::builder()
+      .key(0xA7)
+      .command(Command::Up)
+      .rolling_code(42)
+      .remote_address(u24::new(0xFFAA11))
+      .build()
+      .expect("Failed to build frame")
+  }
+
+  #[test]
+  fn test_waveform() {
+    let waveform = Waveform::for_frame(&frame(), 1);
+    let pulses = waveform.pulses();
+
+    assert!(pulses.len() <= Waveform::with_capacity_for(1).pulses.capacity());
+    assert!(pulses.windows(2).all(|pulses| pulses[0].state != pulses[1].state));
+
+    assert_eq!(pulses[0], Pulse { state: High, duration: TIMING.wake_up_high });
+    assert_eq!(pulses[1], Pulse { state: Low, duration: TIMING.wake_up_low });
+    assert_eq!(pulses[2], Pulse { state: High, duration: 2 * TIMING.symbol_width });
+    assert_eq!(pulses[6], Pulse { state: High, duration: TIMING.software_sync });
+
+    let gaps =
+      pulses[2..].iter().filter(|pulse| pulse.state == Low && pulse.duration >= TIMING.inter_frame_gap).count();
+    assert_eq!(gaps, 2);
+
+    let frame_airtime = |sync_count: u32| {
+      sync_count * 4 * TIMING.symbol_width
+        + TIMING.software_sync
+        + TIMING.symbol_width / 2
+        + 56 * TIMING.symbol_width
+        + TIMING.inter_frame_gap
+    };
+    let airtime = TIMING.wake_up_high + TIMING.wake_up_low + frame_airtime(2) + frame_airtime(7);
+    assert_eq!(waveform.airtime(), Duration::from_micros(airtime.into()));
+  }
+
+  #[test]
+  fn test_waveform_correction() {
+    let waveform = Waveform::for_frame(&frame(), 1);
+
+    let timing = TIMING.with_correction(100);
+    let corrected = Waveform::for_frame_with_timing(&frame(), 1, &timing);
+
+    assert_eq!(waveform.pulses().len(), corrected.pulses().len());
+
+    for (pulse, corrected) in waveform.pulses().iter().zip(corrected.pulses()) {
+      assert_eq!(pulse.state, corrected.state);
+
+      match pulse.state {
+        High => assert_eq!(pulse.duration + 100, corrected.duration),
+        Low => assert_eq!(pulse.duration - 100, corrected.duration),
+      }
+    }
+  }
+}